@@ -0,0 +1,114 @@
+use super::*;
+
+#[derive(Deserialize)]
+pub struct ChatConfig {
+    /// Most recent lines kept and shown
+    lines: usize,
+    /// Seconds a line stays visible while not typing
+    fade_time: f64,
+    text_size: f32,
+    text_color: Rgba<f32>,
+    name_color: Rgba<f32>,
+    /// Rows between the bottom of the screen and the input line
+    bottom_offset: f32,
+}
+
+pub struct ChatLine {
+    name: String,
+    text: String,
+    received: Timer,
+}
+
+impl Game {
+    pub fn receive_chat(&mut self, name: String, text: String) {
+        self.chat.push_back(ChatLine {
+            name,
+            text,
+            received: Timer::new(),
+        });
+        while self.chat.len() > self.assets.config.chat.lines {
+            self.chat.pop_front();
+        }
+    }
+
+    /// Opens the chat input, or sends the typed message if it is open
+    pub fn toggle_chat(&mut self) {
+        if self.ui.is_focused("chat") {
+            self.set_focus(None);
+            let text = std::mem::take(&mut self.chat_input);
+            if !text.trim().is_empty() {
+                self.connection.send(ClientMessage::Chat(text));
+            }
+        } else {
+            self.set_focus(Some("chat"));
+        }
+    }
+
+    pub fn edit_chat_text(&mut self, text: String) {
+        self.chat_input = text.chars().take(self.max_chat_length).collect();
+    }
+
+    pub fn draw_chat(&self, framebuffer: &mut ugli::Framebuffer) {
+        let config = &self.assets.config.chat;
+        let view = self
+            .ui_camera
+            .view_area(self.framebuffer_size)
+            .bounding_box();
+        let font = self.geng.default_font();
+        let left = view.min.x + config.text_size;
+        let mut y = view.min.y + config.bottom_offset;
+        if self.ui.is_focused("chat") {
+            let text = format!("say: {}", self.chat_input);
+            self.geng.draw2d().quad(
+                framebuffer,
+                &self.ui_camera,
+                Aabb2::point(vec2(view.min.x, y))
+                    .extend_right(view.width())
+                    .extend_up(config.text_size),
+                self.assets.config.ui.edit_text_color,
+            );
+            let caret = if self.timer.elapsed().as_secs_f64().fract() < 0.5 {
+                "|"
+            } else {
+                ""
+            };
+            font.draw(
+                framebuffer,
+                &self.ui_camera,
+                &format!("{text}{caret}"),
+                vec2(geng::TextAlign::LEFT, geng::TextAlign::BOTTOM),
+                mat3::translate(vec2(left, y)) * mat3::scale_uniform(config.text_size),
+                config.text_color,
+            );
+        }
+        for line in self.chat.iter().rev() {
+            if !self.ui.is_focused("chat")
+                && line.received.elapsed().as_secs_f64() > config.fade_time
+            {
+                break;
+            }
+            y += config.text_size;
+            let name = format!("{}: ", line.name);
+            let name_width = font
+                .measure(&name, vec2(geng::TextAlign::LEFT, geng::TextAlign::BOTTOM))
+                .map_or(0.0, |aabb| aabb.width());
+            font.draw(
+                framebuffer,
+                &self.ui_camera,
+                &name,
+                vec2(geng::TextAlign::LEFT, geng::TextAlign::BOTTOM),
+                mat3::translate(vec2(left, y)) * mat3::scale_uniform(config.text_size),
+                config.name_color,
+            );
+            font.draw(
+                framebuffer,
+                &self.ui_camera,
+                &line.text,
+                vec2(geng::TextAlign::LEFT, geng::TextAlign::BOTTOM),
+                mat3::translate(vec2(left + name_width * config.text_size, y))
+                    * mat3::scale_uniform(config.text_size),
+                config.text_color,
+            );
+        }
+    }
+}
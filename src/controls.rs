@@ -0,0 +1,547 @@
+use super::*;
+
+#[derive(Deserialize)]
+pub struct ControlsConfig {
+    /// World units per second a limb moves while a direction key is held
+    keyboard_speed: f32,
+    /// How far ahead the baby looks in the direction of movement
+    keyboard_look_distance: f32,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ControlScheme {
+    /// Hover a limb with the cursor and move the mouse
+    Mouse,
+    /// Select a limb and move it with direction keys or the left stick
+    Keyboard,
+}
+
+impl ControlScheme {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Mouse => "mouse",
+            Self::Keyboard => "keyboard / gamepad",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            Self::Mouse => Self::Keyboard,
+            Self::Keyboard => Self::Mouse,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Action {
+    /// Push off the ground with the controlled limb
+    Ground,
+    /// Move the controlled limb in the air
+    Air,
+    GiveUp,
+    LeftArm,
+    RightArm,
+    LeftLeg,
+    RightLeg,
+    Up,
+    Down,
+    Left,
+    Right,
+    EmoteCry,
+    EmoteLaugh,
+    EmoteWave,
+    EmoteRattle,
+}
+
+impl Action {
+    pub fn all() -> impl Iterator<Item = Self> {
+        [
+            Self::Ground,
+            Self::Air,
+            Self::GiveUp,
+            Self::LeftArm,
+            Self::RightArm,
+            Self::LeftLeg,
+            Self::RightLeg,
+            Self::Up,
+            Self::Down,
+            Self::Left,
+            Self::Right,
+            Self::EmoteCry,
+            Self::EmoteLaugh,
+            Self::EmoteWave,
+            Self::EmoteRattle,
+        ]
+        .into_iter()
+    }
+
+    pub fn emote(&self) -> Option<Emote> {
+        match self {
+            Self::EmoteCry => Some(Emote::Cry),
+            Self::EmoteLaugh => Some(Emote::Laugh),
+            Self::EmoteWave => Some(Emote::Wave),
+            Self::EmoteRattle => Some(Emote::Rattle),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Ground => "push off ground",
+            Self::Air => "move in air",
+            Self::GiveUp => "give up",
+            Self::LeftArm => "left arm",
+            Self::RightArm => "right arm",
+            Self::LeftLeg => "left leg",
+            Self::RightLeg => "right leg",
+            Self::Up => "up",
+            Self::Down => "down",
+            Self::Left => "left",
+            Self::Right => "right",
+            Self::EmoteCry => "cry",
+            Self::EmoteLaugh => "laugh",
+            Self::EmoteWave => "wave",
+            Self::EmoteRattle => "rattle",
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Binding {
+    Key(geng::Key),
+    Mouse(geng::MouseButton),
+    Gamepad(gilrs::Button),
+}
+
+impl Binding {
+    fn is_pressed(&self, window: &geng::Window, gamepads: &Gamepads) -> bool {
+        match *self {
+            Self::Key(key) => window.is_key_pressed(key),
+            Self::Mouse(button) => window.is_button_pressed(button),
+            Self::Gamepad(button) => gamepads.is_pressed(button),
+        }
+    }
+
+    /// Binding pressed in this event
+    pub fn from_event(event: &geng::Event) -> Option<Self> {
+        match *event {
+            geng::Event::KeyPress { key } => Some(Self::Key(key)),
+            geng::Event::MousePress { button } => Some(Self::Mouse(button)),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            Self::Key(key) => format!("{key:?}"),
+            Self::Mouse(button) => format!("{button:?} mouse"),
+            Self::Gamepad(button) => format!("{button:?} gamepad"),
+        }
+    }
+}
+
+/// Connected gamepads, `None` when the platform does not support them
+pub struct Gamepads(Option<gilrs::Gilrs>);
+
+impl Gamepads {
+    pub fn new() -> Self {
+        match gilrs::Gilrs::new() {
+            Ok(gilrs) => Self(Some(gilrs)),
+            Err(e) => {
+                log::warn!("gamepads are not available: {e}");
+                Self(None)
+            }
+        }
+    }
+
+    /// Buttons pressed since the last poll
+    pub fn poll(&mut self) -> Vec<gilrs::Button> {
+        let Some(gilrs) = &mut self.0 else {
+            return Vec::new();
+        };
+        let mut pressed = Vec::new();
+        while let Some(event) = gilrs.next_event() {
+            if let gilrs::EventType::ButtonPressed(button, _) = event.event {
+                pressed.push(button);
+            }
+        }
+        pressed
+    }
+
+    /// Triggers count as pressed past the gilrs threshold
+    fn is_pressed(&self, button: gilrs::Button) -> bool {
+        self.0.as_ref().map_or(false, |gilrs| {
+            gilrs
+                .gamepads()
+                .any(|(_, gamepad)| gamepad.is_pressed(button))
+        })
+    }
+
+    /// Left stick of the first gamepad that is pushed out of the dead zone
+    pub fn stick(&self) -> vec2<f32> {
+        const DEAD_ZONE: f32 = 0.2;
+        let Some(gilrs) = &self.0 else {
+            return vec2::ZERO;
+        };
+        gilrs
+            .gamepads()
+            .map(|(_, gamepad)| {
+                vec2(
+                    gamepad.value(gilrs::Axis::LeftStickX),
+                    gamepad.value(gilrs::Axis::LeftStickY),
+                )
+            })
+            .find(|stick| stick.len() > DEAD_ZONE)
+            .unwrap_or(vec2::ZERO)
+    }
+}
+
+/// Buttons bound to each action, stored in preferences
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Bindings(HashMap<Action, Vec<Binding>>);
+
+impl Default for Bindings {
+    fn default() -> Self {
+        use geng::Key;
+        use gilrs::Button;
+        Self(HashMap::from_iter([
+            (
+                Action::Ground,
+                vec![
+                    Binding::Mouse(geng::MouseButton::Left),
+                    Binding::Key(Key::Space),
+                    Binding::Gamepad(Button::RightTrigger2),
+                ],
+            ),
+            (
+                Action::Air,
+                vec![
+                    Binding::Mouse(geng::MouseButton::Right),
+                    Binding::Key(Key::ShiftLeft),
+                    Binding::Gamepad(Button::LeftTrigger2),
+                ],
+            ),
+            (
+                Action::GiveUp,
+                vec![Binding::Key(Key::R), Binding::Gamepad(Button::Select)],
+            ),
+            (Action::EmoteCry, vec![Binding::Key(Key::Digit1)]),
+            (
+                Action::EmoteLaugh,
+                vec![Binding::Key(Key::Digit2), Binding::Gamepad(Button::South)],
+            ),
+            (
+                Action::EmoteWave,
+                vec![Binding::Key(Key::Digit3), Binding::Gamepad(Button::North)],
+            ),
+            (Action::EmoteRattle, vec![Binding::Key(Key::Digit4)]),
+            (
+                Action::LeftArm,
+                vec![Binding::Key(Key::Q), Binding::Gamepad(Button::LeftTrigger)],
+            ),
+            (
+                Action::RightArm,
+                vec![Binding::Key(Key::E), Binding::Gamepad(Button::RightTrigger)],
+            ),
+            (
+                Action::LeftLeg,
+                vec![Binding::Key(Key::Z), Binding::Gamepad(Button::West)],
+            ),
+            (
+                Action::RightLeg,
+                vec![Binding::Key(Key::C), Binding::Gamepad(Button::East)],
+            ),
+            (
+                Action::Up,
+                vec![
+                    Binding::Key(Key::W),
+                    Binding::Key(Key::ArrowUp),
+                    Binding::Gamepad(Button::DPadUp),
+                ],
+            ),
+            (
+                Action::Down,
+                vec![
+                    Binding::Key(Key::S),
+                    Binding::Key(Key::ArrowDown),
+                    Binding::Gamepad(Button::DPadDown),
+                ],
+            ),
+            (
+                Action::Left,
+                vec![
+                    Binding::Key(Key::A),
+                    Binding::Key(Key::ArrowLeft),
+                    Binding::Gamepad(Button::DPadLeft),
+                ],
+            ),
+            (
+                Action::Right,
+                vec![
+                    Binding::Key(Key::D),
+                    Binding::Key(Key::ArrowRight),
+                    Binding::Gamepad(Button::DPadRight),
+                ],
+            ),
+        ]))
+    }
+}
+
+impl Bindings {
+    /// Actions missing from the saved bindings get their default ones
+    pub fn load() -> Self {
+        let mut bindings = Self::default();
+        if let Some(Self(saved)) = preferences::load("bindings") {
+            bindings.0.extend(saved);
+        }
+        bindings
+    }
+
+    pub fn save(&self) {
+        preferences::save("bindings", self);
+    }
+
+    pub fn get(&self, action: Action) -> &[Binding] {
+        self.0
+            .get(&action)
+            .map_or(&[], |bindings| bindings.as_slice())
+    }
+
+    /// Replaces the binding in the given slot of the action, or adds one past the last slot.
+    /// The binding is taken off any other action so one press does one thing
+    pub fn set(&mut self, action: Action, slot: usize, binding: Binding) {
+        for (&other, bindings) in &mut self.0 {
+            if other != action {
+                bindings.retain(|&bound| bound != binding);
+            }
+        }
+        let bindings = self.0.entry(action).or_default();
+        if bindings.contains(&binding) {
+            return;
+        }
+        if let Some(old) = bindings.get_mut(slot) {
+            *old = binding;
+        } else {
+            bindings.push(binding);
+        }
+    }
+
+    pub fn remove(&mut self, action: Action, slot: usize) {
+        if let Some(bindings) = self.0.get_mut(&action) {
+            if slot < bindings.len() {
+                bindings.remove(slot);
+            }
+        }
+    }
+
+    pub fn is_pressed(&self, window: &geng::Window, gamepads: &Gamepads, action: Action) -> bool {
+        self.get(action)
+            .iter()
+            .any(|binding| binding.is_pressed(window, gamepads))
+    }
+
+    pub fn is_bound(&self, binding: Binding, action: Action) -> bool {
+        self.get(action).contains(&binding)
+    }
+}
+
+/// Device independent input consumed by `Game::baby_control`
+pub struct ControlInput {
+    /// World position the baby looks at and picks the hovered limb by
+    pub aim: vec2<f32>,
+    /// Limb picked explicitly instead of by aim
+    pub limb: Option<Limb>,
+    /// Movement of the controlled limb this frame in world units
+    pub delta: vec2<f32>,
+    /// Pushing off the ground with the limb
+    pub ground: bool,
+    /// Moving the limb in the air
+    pub air: bool,
+}
+
+/// Finger on the screen, in screen coordinates
+pub struct TouchPoint {
+    pub id: u64,
+    pub pos: vec2<f32>,
+    /// Position at the previous frame
+    pub prev: vec2<f32>,
+}
+
+impl Game {
+    /// Touch works regardless of the selected scheme.
+    /// A single finger moves the limb under it in the air,
+    /// with a second finger down the first one plants the limb and the second one drags.
+    fn touch_input(&mut self) -> Option<ControlInput> {
+        let to_world = |pos| self.camera.screen_to_world(self.framebuffer_size, pos);
+        let planted = self.touches.first()?;
+        let (dragging, ground) = match self.touches.get(1) {
+            Some(second) => (second, true),
+            None => (planted, false),
+        };
+        let input = ControlInput {
+            aim: to_world(planted.pos),
+            limb: None,
+            delta: (to_world(dragging.pos) - to_world(dragging.prev))
+                * self.assets.config.sensitivity
+                * self.settings.sensitivity,
+            ground,
+            air: !ground,
+        };
+        for touch in &mut self.touches {
+            touch.prev = touch.pos;
+        }
+        Some(input)
+    }
+
+    pub fn control_input(&mut self, cursor_pos: vec2<f32>, delta_time: f32) -> ControlInput {
+        if let Some(input) = self.touch_input() {
+            return input;
+        }
+        let window = self.geng.window();
+        let bindings = &self.bindings;
+        let gamepads = &self.gamepads;
+        if window.is_editing_text() {
+            // Typing in chat should not move the baby
+            return ControlInput {
+                aim: cursor_pos,
+                limb: None,
+                delta: vec2::ZERO,
+                ground: false,
+                air: false,
+            };
+        }
+        match self.control_scheme {
+            ControlScheme::Mouse => ControlInput {
+                aim: cursor_pos,
+                limb: None,
+                delta: (cursor_pos - self.prev_cursor_pos)
+                    * self.assets.config.sensitivity
+                    * self.settings.sensitivity,
+                ground: bindings.is_pressed(window, gamepads, Action::Ground),
+                air: bindings.is_pressed(window, gamepads, Action::Air),
+            },
+            ControlScheme::Keyboard => {
+                for (action, limb) in [
+                    (Action::LeftArm, Limb::LeftArm),
+                    (Action::RightArm, Limb::RightArm),
+                    (Action::LeftLeg, Limb::LeftLeg),
+                    (Action::RightLeg, Limb::RightLeg),
+                ] {
+                    if bindings.is_pressed(window, gamepads, action) {
+                        self.keyboard_limb = limb;
+                    }
+                }
+                let mut direction = vec2::<f32>::ZERO;
+                for (action, dir) in [
+                    (Action::Left, vec2(-1.0, 0.0)),
+                    (Action::Right, vec2(1.0, 0.0)),
+                    (Action::Down, vec2(0.0, -1.0)),
+                    (Action::Up, vec2(0.0, 1.0)),
+                ] {
+                    if bindings.is_pressed(window, gamepads, action) {
+                        direction += dir;
+                    }
+                }
+                direction += gamepads.stick();
+                // Full speed diagonally but slower with the stick pushed halfway
+                let direction = if direction.len() > 1.0 {
+                    direction.normalize()
+                } else {
+                    direction
+                };
+                let config = &self.assets.config.controls;
+                let pos = self.baby.as_ref().map_or(vec2::ZERO, |baby| baby.pos);
+                let look = if direction == vec2::ZERO {
+                    vec2(0.0, 1.0)
+                } else {
+                    direction.normalize()
+                };
+                ControlInput {
+                    aim: pos + look * config.keyboard_look_distance,
+                    limb: Some(self.keyboard_limb),
+                    delta: direction * config.keyboard_speed * delta_time,
+                    ground: bindings.is_pressed(window, gamepads, Action::Ground),
+                    air: bindings.is_pressed(window, gamepads, Action::Air),
+                }
+            }
+        }
+    }
+
+    /// Handles a key or button going down: binds it to the action picked for rebinding,
+    /// otherwise performs the one-shot actions bound to it.
+    /// Returns whether the press was used up by rebinding
+    pub fn press_binding(&mut self, binding: Binding) -> bool {
+        if let Some((action, slot)) = self.rebinding.take() {
+            match binding {
+                Binding::Key(geng::Key::Escape) => return true,
+                Binding::Key(geng::Key::Backspace) => self.bindings.remove(action, slot),
+                _ => self.bindings.set(action, slot, binding),
+            }
+            self.bindings.save();
+            return true;
+        }
+        if self.geng.window().is_editing_text() {
+            return false;
+        }
+        if self.baby.is_some() && self.bindings.is_bound(binding, Action::GiveUp) {
+            self.baby = None;
+            self.play_sfx(&self.assets.stop);
+            self.connection.send(ClientMessage::Despawn);
+        }
+        for action in Action::all() {
+            if let Some(emote) = action.emote() {
+                if self.bindings.is_bound(binding, action) {
+                    self.send_emote(emote);
+                }
+            }
+        }
+        false
+    }
+
+    pub fn update_gamepads(&mut self) {
+        for button in self.gamepads.poll() {
+            self.press_binding(Binding::Gamepad(button));
+        }
+    }
+
+    pub fn bindings_menu(&self) -> Vec<MenuWidget> {
+        let mut rows = Vec::new();
+        for action in Action::all() {
+            let bindings = self.bindings.get(action);
+            let mut row = vec![Widget::Label(action.name().to_owned())];
+            // The slot past the last binding adds a new one
+            for slot in 0..=bindings.len() {
+                let name = if self.rebinding == Some((action, slot)) {
+                    "press a key or button".to_owned()
+                } else {
+                    bindings
+                        .get(slot)
+                        .map_or("+".to_owned(), |binding| binding.name())
+                };
+                row.push(Widget::Button(name, MenuItemAction::Rebind(action, slot)));
+            }
+            rows.push(Widget::Row(row));
+        }
+        vec![
+            Widget::Label(
+                "click a binding to change it or + to add one, Backspace removes, Esc cancels"
+                    .to_owned(),
+            ),
+            Widget::List {
+                id: "bindings",
+                rows: 10,
+                items: rows,
+            },
+            Widget::Row(vec![
+                Widget::Button(
+                    "reset to defaults".to_owned(),
+                    MenuItemAction::ResetBindings,
+                ),
+                Widget::Button(
+                    "back".to_owned(),
+                    MenuItemAction::OpenScreen(MenuScreen::Main),
+                ),
+            ]),
+        ]
+    }
+}
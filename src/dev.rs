@@ -0,0 +1,74 @@
+use super::*;
+
+/// Seconds between checks of the assets directory
+const CHECK_INTERVAL: f64 = 0.5;
+
+type AssetsFuture = std::pin::Pin<Box<dyn std::future::Future<Output = anyhow::Result<Assets>>>>;
+
+/// Reloads the assets whenever a file in the assets directory changes, enabled with `--dev`
+pub struct AssetWatcher {
+    last_modified: Option<std::time::SystemTime>,
+    check_timer: Timer,
+    loading: Option<AssetsFuture>,
+}
+
+impl AssetWatcher {
+    pub fn new() -> Self {
+        Self {
+            last_modified: latest_modification(&run_dir().join("assets")),
+            check_timer: Timer::new(),
+            loading: None,
+        }
+    }
+}
+
+/// Latest modification time of the file or anything inside the directory
+fn latest_modification(path: &std::path::Path) -> Option<std::time::SystemTime> {
+    let metadata = std::fs::metadata(path).ok()?;
+    if !metadata.is_dir() {
+        return metadata.modified().ok();
+    }
+    std::fs::read_dir(path)
+        .ok()?
+        .filter_map(|entry| latest_modification(&entry.ok()?.path()))
+        .max()
+}
+
+impl Game {
+    /// Swaps in freshly loaded assets, keeping the old ones if loading fails
+    pub fn update_asset_watcher(&mut self) {
+        let Some(watcher) = &mut self.asset_watcher else {
+            return;
+        };
+        if let Some(loading) = &mut watcher.loading {
+            let mut context = std::task::Context::from_waker(futures::task::noop_waker_ref());
+            let std::task::Poll::Ready(result) =
+                std::future::Future::poll(loading.as_mut(), &mut context)
+            else {
+                return;
+            };
+            watcher.loading = None;
+            match result {
+                Ok(assets) => {
+                    self.assets = Rc::new(assets);
+                    log::info!("reloaded assets");
+                }
+                Err(e) => log::error!("failed to reload assets: {e:#}"),
+            }
+            return;
+        }
+        if watcher.check_timer.elapsed().as_secs_f64() < CHECK_INTERVAL {
+            return;
+        }
+        watcher.check_timer.reset();
+        let modified = latest_modification(&run_dir().join("assets"));
+        if modified == watcher.last_modified {
+            return;
+        }
+        watcher.last_modified = modified;
+        let manager = self.geng.asset_manager().clone();
+        watcher.loading = Some(Box::pin(async move {
+            manager.load(run_dir().join("assets")).await
+        }));
+    }
+}
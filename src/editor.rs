@@ -0,0 +1,242 @@
+use super::*;
+
+#[derive(Deserialize)]
+pub struct EditorConfig {
+    grid: f32,
+    pan_speed: f32,
+    zoom_speed: f32,
+    min_fov: f32,
+    max_fov: f32,
+    preview_color: Rgba<f32>,
+    text_color: Rgba<f32>,
+    text_size: f32,
+    start_y: f32,
+}
+
+pub struct Editor {
+    pub track: Track,
+    kind: ObstacleKind,
+    size: vec2<f32>,
+    /// World position grabbed for panning the camera
+    grab: Option<vec2<f32>>,
+}
+
+impl Editor {
+    pub fn new(default_theme: &str) -> Self {
+        Self {
+            track: preferences::load("editor_track").unwrap_or_else(|| Track {
+                name: "custom".to_owned(),
+                length: 20.0,
+                width: 10.0,
+                theme: default_theme.to_owned(),
+                parents_height: 3.0,
+                path: Vec::new(),
+                smoothing: 0,
+                checkpoints: Vec::new(),
+                finish: 19.0,
+                obstacles: Vec::new(),
+                decorations: Vec::new(),
+                segment_cache: default(),
+            }),
+            kind: ObstacleKind::Pillow,
+            size: vec2(2.0, 1.0),
+            grab: None,
+        }
+    }
+
+    fn snap(&self, pos: vec2<f32>, grid: f32) -> vec2<f32> {
+        pos.map(|x| (x / grid).round() * grid)
+    }
+
+    fn new_obstacle(&self, pos: vec2<f32>) -> Obstacle {
+        Obstacle {
+            kind: self.kind,
+            pos,
+            size: self.size,
+            angle: match self.kind {
+                ObstacleKind::Slope => -90.0,
+                _ => 0.0,
+            },
+        }
+    }
+
+    fn set_length(&mut self, length: f32) {
+        self.track.length = length.max(2.0);
+        self.track.finish = self.track.length - 1.0;
+        self.track.path_changed();
+    }
+}
+
+impl Game {
+    fn editor_cursor(&self) -> vec2<f32> {
+        self.camera.screen_to_world(
+            self.framebuffer_size,
+            self.geng
+                .window()
+                .cursor_position()
+                .unwrap_or(vec2::ZERO)
+                .map(|x| x as f32),
+        )
+    }
+
+    pub fn handle_editor_event(&mut self, event: geng::Event) {
+        let cursor = self.editor_cursor();
+        let config = &self.assets.config.editor;
+        let Some(editor) = &mut self.editor else {
+            return;
+        };
+        match event {
+            geng::Event::MousePress {
+                button: geng::MouseButton::Left,
+            } => {
+                let obstacle = editor.new_obstacle(editor.snap(cursor, config.grid));
+                editor.track.obstacles.push(obstacle);
+            }
+            geng::Event::MousePress {
+                button: geng::MouseButton::Right,
+            } => {
+                if let Some(index) = editor
+                    .track
+                    .obstacles
+                    .iter()
+                    .rposition(|obstacle| obstacle.aabb().contains(cursor))
+                {
+                    editor.track.obstacles.remove(index);
+                }
+            }
+            geng::Event::MousePress {
+                button: geng::MouseButton::Middle,
+            } => editor.grab = Some(cursor),
+            geng::Event::MouseRelease {
+                button: geng::MouseButton::Middle,
+            } => editor.grab = None,
+            geng::Event::Wheel { delta } => {
+                if let Camera2dFov::MinSide(fov) = &mut self.camera.fov {
+                    *fov = (*fov * (-delta as f32 * config.zoom_speed).exp())
+                        .clamp(config.min_fov, config.max_fov);
+                }
+            }
+            geng::Event::KeyPress { key } => match key {
+                geng::Key::Tab => {
+                    let kinds: Vec<ObstacleKind> = ObstacleKind::all().collect();
+                    let index = kinds.iter().position(|&kind| kind == editor.kind).unwrap();
+                    editor.kind = kinds[(index + 1) % kinds.len()];
+                }
+                geng::Key::Q => {
+                    editor.size =
+                        (editor.size - vec2::splat(config.grid)).map(|x| x.max(config.grid))
+                }
+                geng::Key::E => editor.size += vec2::splat(config.grid),
+                geng::Key::BracketLeft => editor.set_length(editor.track.length - 1.0),
+                geng::Key::BracketRight => editor.set_length(editor.track.length + 1.0),
+                geng::Key::Minus => editor.track.width = (editor.track.width - 1.0).max(2.0),
+                geng::Key::Equal => editor.track.width += 1.0,
+                geng::Key::S => {
+                    preferences::save("editor_track", &editor.track);
+                    match toml::to_string_pretty(&editor.track) {
+                        Ok(data) => {
+                            if let Err(e) = file_dialog::save("track.toml", data.as_bytes()) {
+                                log::error!("failed to export track: {e}");
+                            }
+                        }
+                        Err(e) => log::error!("failed to serialize track: {e}"),
+                    }
+                }
+                geng::Key::T => {
+                    self.baby = Some(Baby::new(Some(&self.assets), vec2(0.0, config.start_y)));
+                    self.next_checkpoint = 0;
+                    self.progress = 0.0;
+                    self.play_sfx(&self.assets.start);
+                    self.timer.reset();
+                }
+                geng::Key::H => {
+                    preferences::save("editor_track", &editor.track);
+                    self.connection
+                        .send(ClientMessage::UploadTrack(editor.track.clone()));
+                    self.hosted_track = Some(CUSTOM_TRACK.to_owned());
+                    self.custom_track_uploaded = true;
+                    self.host_race = true;
+                    self.track_error = None;
+                    self.editor = None;
+                    self.camera.fov = Camera2dFov::MinSide(self.assets.config.camera.fov);
+                }
+                geng::Key::Escape => {
+                    preferences::save("editor_track", &editor.track);
+                    self.editor = None;
+                    self.camera.fov = Camera2dFov::MinSide(self.assets.config.camera.fov);
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    /// Camera panning while not test-crawling
+    pub fn update_editor_camera(&mut self, cursor: vec2<f32>, delta_time: f32) {
+        let Some(editor) = &self.editor else {
+            return;
+        };
+        if let Some(grab) = editor.grab {
+            self.camera.center += grab - cursor;
+        }
+        let mut direction = vec2::<f32>::ZERO;
+        let window = self.geng.window();
+        if window.is_key_pressed(geng::Key::ArrowLeft) || window.is_key_pressed(geng::Key::A) {
+            direction.x -= 1.0;
+        }
+        if window.is_key_pressed(geng::Key::ArrowRight) || window.is_key_pressed(geng::Key::D) {
+            direction.x += 1.0;
+        }
+        if window.is_key_pressed(geng::Key::ArrowDown) {
+            direction.y -= 1.0;
+        }
+        if window.is_key_pressed(geng::Key::ArrowUp) || window.is_key_pressed(geng::Key::W) {
+            direction.y += 1.0;
+        }
+        self.camera.center += direction * self.assets.config.editor.pan_speed * delta_time;
+    }
+
+    pub fn draw_editor(&self, framebuffer: &mut ugli::Framebuffer) {
+        let Some(editor) = &self.editor else {
+            return;
+        };
+        let config = &self.assets.config.editor;
+        if self.baby.is_none() {
+            let preview = editor.new_obstacle(editor.snap(self.editor_cursor(), config.grid));
+            self.geng.draw2d().quad(
+                framebuffer,
+                &self.camera,
+                preview.aabb(),
+                config.preview_color,
+            );
+        }
+        let lines = if self.baby.is_some() {
+            vec!["testing, R to stop".to_owned()]
+        } else {
+            vec![
+                format!("obstacle: {:?} (Tab)", editor.kind),
+                format!("size: {}x{} (Q/E)", editor.size.x, editor.size.y),
+                format!("length: {} ([/])", editor.track.length),
+                format!("width: {} (-/=)", editor.track.width),
+                "LMB place, RMB remove, MMB/arrows pan, wheel zoom".to_owned(),
+                "T test, S save, H host race, Esc exit".to_owned(),
+            ]
+        };
+        let top_left = self
+            .ui_camera
+            .view_area(self.framebuffer_size)
+            .bounding_box()
+            .top_left();
+        for (i, line) in lines.iter().enumerate() {
+            self.geng.default_font().draw(
+                framebuffer,
+                &self.ui_camera,
+                line,
+                vec2(geng::TextAlign::LEFT, geng::TextAlign::TOP),
+                mat3::translate(top_left + vec2(0.5, -0.5 - i as f32 * config.text_size))
+                    * mat3::scale_uniform(config.text_size),
+                config.text_color,
+            );
+        }
+    }
+}
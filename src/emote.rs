@@ -0,0 +1,71 @@
+use super::*;
+
+#[derive(Deserialize)]
+pub struct EmoteConfig {
+    /// Seconds our own emote stays visible, others are cleared by the server
+    duration: f64,
+    /// Bubble position relative to the baby, next to the nametag
+    offset: vec2<f32>,
+    text_size: f32,
+    padding: f32,
+    bubble_color: Rgba<f32>,
+    text_color: Rgba<f32>,
+    texts: HashMap<Emote, String>,
+}
+
+impl Game {
+    pub fn send_emote(&mut self, emote: Emote) {
+        self.emote = Some((emote, Timer::new()));
+        self.connection.send(ClientMessage::Emote(emote));
+    }
+
+    pub fn update_emote(&mut self) {
+        if let Some((_, timer)) = &self.emote {
+            if timer.elapsed().as_secs_f64() > self.assets.config.emote.duration {
+                self.emote = None;
+            }
+        }
+    }
+
+    /// Speech bubble over the baby at `pos`
+    pub fn draw_emote(&self, framebuffer: &mut ugli::Framebuffer, pos: vec2<f32>, emote: Emote) {
+        let config = &self.assets.config.emote;
+        let Some(text) = config.texts.get(&emote) else {
+            return;
+        };
+        let font = self.geng.default_font();
+        let align = vec2(geng::TextAlign::LEFT, geng::TextAlign::BOTTOM);
+        let size = font
+            .measure(text, align)
+            .map_or(vec2::ZERO, |aabb| aabb.size())
+            * config.text_size;
+        let bottom_left = pos + config.offset;
+        let bubble = Aabb2::point(bottom_left)
+            .extend_positive(size)
+            .extend_uniform(config.padding);
+        self.geng
+            .draw2d()
+            .quad(framebuffer, &self.camera, bubble, config.bubble_color);
+        // Tail pointing down at the baby
+        self.geng.draw2d().draw2d(
+            framebuffer,
+            &self.camera,
+            &draw2d::Polygon::new(
+                vec![
+                    vec2(bubble.min.x, bubble.min.y),
+                    vec2(bubble.min.x + config.padding * 2.0, bubble.min.y),
+                    vec2(bubble.min.x, bubble.min.y - config.padding * 2.0),
+                ],
+                config.bubble_color,
+            ),
+        );
+        font.draw(
+            framebuffer,
+            &self.camera,
+            text,
+            align,
+            mat3::translate(bottom_left) * mat3::scale_uniform(config.text_size),
+            config.text_color,
+        );
+    }
+}
@@ -3,34 +3,93 @@ use super::*;
 pub type ClientId = u64;
 pub type RaceId = u64;
 
+/// Track id of the track uploaded by the host
+pub const CUSTOM_TRACK: &str = "custom";
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Emote {
+    Cry,
+    Laugh,
+    Wave,
+    Rattle,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HostedRace {
     pub joined_players: Vec<ClientId>,
 }
 
+/// Part of the track a baby is confined to in lane mode
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Lane {
+    pub center: f32,
+    pub width: f32,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ClientServerState {
     pub name: String,
     pub baby: Option<Baby>,
     pub hosting_race: bool,
+    /// Whether the race being hosted uses lanes
+    pub lanes: bool,
+    pub lane: Option<Lane>,
+    /// Track id of the race being hosted
+    pub track: String,
+    /// Distance crawled along the track in the current race
+    pub progress: f32,
+    /// Seconds it took to finish the current race
+    pub finish_time: Option<f32>,
     pub joined: Option<ClientId>,
     pub race_id: Option<RaceId>,
+    /// Race being watched
+    pub spectating: Option<RaceId>,
+    /// Emote currently shown over the baby
+    pub emote: Option<Emote>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ServerMessage {
     Spawn(vec2<f32>),
+    /// Collision push to apply to your baby
+    Bump(vec2<f32>),
+    /// Ids of the tracks available for hosting
+    TrackList(Vec<String>),
+    /// Track of the race you are about to spawn in
+    Track(Track),
+    /// Uploaded custom track failed validation
+    TrackRejected(String),
     StateSync {
         clients: BTreeMap<ClientId, ClientServerState>,
     },
     Auth {
         id: ClientId,
+        /// Longer chat messages get cut by the server
+        max_chat_length: usize,
     },
     Name(String),
+    /// Message in the chat room you are in, `from` is `None` for server notices
+    Chat {
+        from: Option<ClientId>,
+        name: String,
+        text: String,
+    },
+    /// Confirms which race you are watching and how often to sync,
+    /// `None` also when the server refused to let you watch
+    Spectate {
+        race: Option<RaceId>,
+        sync_interval: f32,
+    },
     RaceResult {
         rank: usize,
         time: f32,
     },
+    /// Your player id is on the ban list, nothing else will be accepted
+    Banned,
+    /// An admin disconnected you, nothing else will be accepted
+    Kicked,
+    /// An admin ended the race you were in
+    RaceEnded,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -40,6 +99,16 @@ pub enum ClientMessage {
     Despawn,
     Finish,
     Name(String),
+    /// Custom track to use for the race you are hosting
+    UploadTrack(Track),
+    Chat(String),
+    Emote(Emote),
+    /// Watch a race instead of receiving the whole server state
+    Spectate(Option<RaceId>),
+    /// Persistent player id, sent right after connecting
+    Identify(String),
+    /// Flag a player for the server admin to look at
+    Report(ClientId),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -47,4 +116,7 @@ pub struct ClientState {
     pub baby: Option<Baby>,
     pub join_race: Option<ClientId>,
     pub host_race: bool,
+    pub lanes: bool,
+    pub track: Option<String>,
+    pub progress: f32,
 }
@@ -0,0 +1,139 @@
+use super::*;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum LobbySort {
+    Host,
+    Players,
+    Track,
+}
+
+struct LobbyEntry<'a> {
+    /// Hosting client, `None` for races already in progress
+    host: Option<ClientId>,
+    name: &'a str,
+    players: usize,
+    track: &'a str,
+    status: String,
+}
+
+impl Game {
+    fn lobby_entries(&self) -> Vec<LobbyEntry> {
+        let mut entries = Vec::new();
+        for (&id, client) in &self.others {
+            if id == self.my_id || !client.hosting_race {
+                continue;
+            }
+            entries.push(LobbyEntry {
+                host: Some(id),
+                name: &client.name,
+                players: 1 + self
+                    .others
+                    .values()
+                    .filter(|other| other.joined == Some(id))
+                    .count(),
+                track: &client.track,
+                status: if client.lanes {
+                    "open, lanes".to_owned()
+                } else {
+                    "open".to_owned()
+                },
+            });
+        }
+        for racers in self.active_races().values() {
+            let first = &self.others[&racers[0]];
+            let names: Vec<&str> = racers
+                .iter()
+                .map(|id| self.others[id].name.as_str())
+                .collect();
+            entries.push(LobbyEntry {
+                host: None,
+                name: &first.name,
+                players: racers.len(),
+                track: &first.track,
+                status: format!("racing: {}", names.join(", ")),
+            });
+        }
+        let filter = self.lobby_filter.to_lowercase();
+        entries.retain(|entry| {
+            entry.name.to_lowercase().contains(&filter)
+                || entry.track.to_lowercase().contains(&filter)
+        });
+        let (sort, descending) = self.lobby_sort;
+        entries.sort_by(|a, b| {
+            let ordering = match sort {
+                LobbySort::Host => a.name.cmp(b.name),
+                LobbySort::Players => a.players.cmp(&b.players),
+                LobbySort::Track => a.track.cmp(b.track),
+            };
+            // Joinable races first regardless of order
+            b.host.is_some().cmp(&a.host.is_some()).then(if descending {
+                ordering.reverse()
+            } else {
+                ordering
+            })
+        });
+        entries
+    }
+
+    /// Filter box, sortable column headers and the list of races
+    pub fn lobby_browser(&self) -> Vec<MenuWidget> {
+        let header = |text: &str, sort: LobbySort| {
+            let arrow = match self.lobby_sort {
+                (current, false) if current == sort => " ^",
+                (current, true) if current == sort => " v",
+                _ => "",
+            };
+            Widget::Button(format!("{text}{arrow}"), MenuItemAction::SortLobby(sort))
+        };
+        let rows: Vec<MenuWidget> = self
+            .lobby_entries()
+            .into_iter()
+            .map(|entry| {
+                let cells = [
+                    entry.name.to_owned(),
+                    entry.players.to_string(),
+                    entry.track.to_owned(),
+                    entry.status,
+                ];
+                Widget::Row(
+                    cells
+                        .into_iter()
+                        .map(|text| match entry.host {
+                            Some(id) => Widget::Button(text, MenuItemAction::Join(id)),
+                            None => Widget::Label(text),
+                        })
+                        .collect(),
+                )
+            })
+            .collect();
+        vec![
+            Widget::TextInput {
+                id: "filter",
+                text: format!("filter: {}", self.lobby_filter),
+                action: MenuItemAction::EditFilter,
+            },
+            Widget::Row(vec![
+                header("host", LobbySort::Host),
+                header("players", LobbySort::Players),
+                header("track", LobbySort::Track),
+                Widget::Label("status".to_owned()),
+            ]),
+            if rows.is_empty() {
+                Widget::Label("no races hosted".to_owned())
+            } else {
+                Widget::List {
+                    id: "races",
+                    rows: 5,
+                    items: rows,
+                }
+            },
+        ]
+    }
+
+    pub fn sort_lobby(&mut self, sort: LobbySort) {
+        self.lobby_sort = match self.lobby_sort {
+            (current, descending) if current == sort => (sort, !descending),
+            _ => (sort, false),
+        };
+    }
+}
@@ -1,13 +1,35 @@
 #![allow(dead_code)]
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, VecDeque};
 
 use geng::prelude::*;
 
+mod chat;
+mod controls;
+mod dev;
+mod editor;
+mod emote;
 mod interop;
+mod lobby;
+mod moderation;
 #[cfg(not(target_arch = "wasm32"))]
 mod server;
+mod settings;
+mod spectator;
+mod track;
+mod ui;
 
+use chat::*;
+use controls::*;
+use dev::*;
+use editor::*;
+use emote::*;
 use interop::*;
+use lobby::*;
+use moderation::*;
+use settings::*;
+use spectator::*;
+use track::*;
+use ui::*;
 
 #[derive(clap::Parser)]
 struct CliArgs {
@@ -15,6 +37,9 @@ struct CliArgs {
     pub server: Option<String>,
     #[clap(long)]
     pub connect: Option<String>,
+    /// Reload assets when they change on disk
+    #[clap(long)]
+    pub dev: bool,
     #[clap(flatten)]
     geng: geng::CliArgs,
 }
@@ -28,7 +53,9 @@ struct LimbConfig {
     /// relative to body_pos
     touch_ground: vec2<f32>,
     /// wether to flip the texture
+    #[serde(default)]
     flip: bool,
+    #[serde(default)]
     texture_origin: vec2<f32>,
 }
 
@@ -38,11 +65,72 @@ struct BabyConfig {
     head_offset: vec2<f32>,
     limb_rotation_limit: f32,
     limb_length: f32,
+    /// collision circle radius around limb ends, relative to baby radius
+    limb_collision_radius: f32,
     max_head_rotation: f32,
     head_rotation_k: f32,
     limbs: HashMap<Limb, LimbConfig>,
 }
 
+impl BabyConfig {
+    fn validate(&self) -> Result<(), String> {
+        for limb in Limb::all() {
+            if !self.limbs.contains_key(&limb) {
+                return Err(format!("baby.limbs.{limb:?} is missing"));
+            }
+        }
+        let positive = [
+            ("baby.radius", self.radius),
+            ("baby.limb_length", self.limb_length),
+        ];
+        for (field, value) in positive {
+            if !(value.is_finite() && value > 0.0) {
+                return Err(format!("{field} must be a positive number"));
+            }
+        }
+        if !(self.limb_collision_radius.is_finite() && self.limb_collision_radius >= 0.0) {
+            return Err("baby.limb_collision_radius must not be negative".to_owned());
+        }
+        let degrees = [
+            ("baby.limb_rotation_limit", self.limb_rotation_limit),
+            ("baby.max_head_rotation", self.max_head_rotation),
+        ];
+        for (field, value) in degrees {
+            if !(0.0..=180.0).contains(&value) {
+                return Err(format!("{field} must be between 0 and 180 degrees"));
+            }
+        }
+        if !(0.0..=1.0).contains(&self.head_rotation_k) {
+            return Err("baby.head_rotation_k must be between 0 and 1".to_owned());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Deserialize)]
+struct ObstacleConfig {
+    color: Rgba<f32>,
+    /// whether babies can pass through
+    #[serde(default)]
+    solid: bool,
+    /// crawling speed multiplier while on top
+    speed: f32,
+    /// sliding speed while on top
+    #[serde(default)]
+    slide: f32,
+}
+
+#[derive(Deserialize)]
+struct DecorationConfig {
+    color: Rgba<f32>,
+}
+
+#[derive(Deserialize)]
+struct ThemeConfig {
+    background_color: Rgba<f32>,
+    ruler_color: Rgba<f32>,
+}
+
 #[derive(Deserialize)]
 struct CameraConfig {
     fov: f32,
@@ -73,12 +161,24 @@ struct UiConfig {
     rank_color: Rgba<f32>,
     rank_offset: f32,
     rank_size: f32,
+    standings_size: f32,
+    standings_color: Rgba<f32>,
+    standings_highlight_color: Rgba<f32>,
+    standings_bg_color: Rgba<f32>,
+    progress_strip_width: f32,
+    progress_strip_margin: f32,
+    progress_strip_color: Rgba<f32>,
+    progress_marker_radius: f32,
+    spectators_size: f32,
+    slider_width: f32,
+    slider_color: Rgba<f32>,
+    slider_fill_color: Rgba<f32>,
+    scrollbar_width: f32,
+    scrollbar_color: Rgba<f32>,
 }
 
-#[derive(geng::asset::Load, Deserialize)]
-#[load(serde = "toml")]
+#[derive(Deserialize)]
 struct Config {
-    parents_height: f32,
     tutorial_size: f32,
     nametag_offset: f32,
     nametag_color: Rgba<f32>,
@@ -89,8 +189,88 @@ struct Config {
     camera: CameraConfig,
     sensitivity: f32,
     baby: BabyConfig,
-    ruler_color: Rgba<f32>,
-    track_len: f32,
+    wall_width: f32,
+    wall_color: Rgba<f32>,
+    lane_color: Rgba<f32>,
+    lane_line_width: f32,
+    lane_name_size: f32,
+    lane_name_offset: f32,
+    obstacles: HashMap<ObstacleKind, ObstacleConfig>,
+    decorations: HashMap<DecorationKind, DecorationConfig>,
+    finish_line_width: f32,
+    finish_line_color: Rgba<f32>,
+    checkpoint_color: Rgba<f32>,
+    next_checkpoint_color: Rgba<f32>,
+    /// used when the track's theme is unknown
+    default_theme: String,
+    themes: HashMap<String, ThemeConfig>,
+    chat: ChatConfig,
+    controls: ControlsConfig,
+    emote: EmoteConfig,
+    editor: EditorConfig,
+    spectator: SpectatorConfig,
+}
+
+impl Config {
+    fn validate(&self) -> Result<(), String> {
+        self.baby.validate()?;
+        let positive = [
+            ("camera.fov", self.camera.fov),
+            ("ui.fov", self.ui.fov),
+            ("sensitivity", self.sensitivity),
+        ];
+        for (field, value) in positive {
+            if !(value.is_finite() && value > 0.0) {
+                return Err(format!("{field} must be a positive number"));
+            }
+        }
+        if !(self.camera.speed.is_finite() && self.camera.speed >= 0.0) {
+            return Err("camera.speed must not be negative".to_owned());
+        }
+        for kind in ObstacleKind::all() {
+            let Some(obstacle) = self.obstacles.get(&kind) else {
+                return Err(format!("obstacles.{kind:?} is missing"));
+            };
+            if !(obstacle.speed.is_finite() && obstacle.speed >= 0.0) {
+                return Err(format!("obstacles.{kind:?}.speed must not be negative"));
+            }
+        }
+        for kind in DecorationKind::all() {
+            if !self.decorations.contains_key(&kind) {
+                return Err(format!("decorations.{kind:?} is missing"));
+            }
+        }
+        if !self.themes.contains_key(&self.default_theme) {
+            return Err(format!(
+                "default_theme {:?} is not in themes",
+                self.default_theme
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl geng::asset::Load for Config {
+    type Options = ();
+    fn load(
+        _manager: &geng::asset::Manager,
+        path: &std::path::Path,
+        _options: &Self::Options,
+    ) -> geng::asset::Future<Self> {
+        let path = path.to_owned();
+        async move {
+            let config: Self = file::load_detect(&path)
+                .await
+                .map_err(|e| e.context(format!("failed to load {path:?}")))?;
+            config
+                .validate()
+                .map_err(|e| anyhow::anyhow!("invalid {path:?}: {e}"))?;
+            Ok(config)
+        }
+        .boxed_local()
+    }
+
+    const DEFAULT_EXT: Option<&'static str> = Some("toml");
 }
 
 #[derive(Deref)]
@@ -204,12 +384,92 @@ impl Baby {
             },
         }
     }
+
+    /// Collision circles (center, radius) in world coordinates: body and limb ends
+    fn collision_circles(&self, config: &BabyConfig) -> Vec<(vec2<f32>, f32)> {
+        let mut circles = vec![(self.pos, self.radius)];
+        for (limb, limb_state) in &self.limbs {
+            let limb_config = &config.limbs[limb];
+            let end = limb_config.body_pos.rotate(self.rotation)
+                + limb_config
+                    .touch_ground
+                    .rotate(limb_state.rotation + self.rotation);
+            circles.push((
+                self.pos + end * self.radius,
+                config.limb_collision_radius * self.radius,
+            ));
+        }
+        circles
+    }
+}
+
+struct Standing<'a> {
+    name: &'a str,
+    progress: f32,
+    finish_time: Option<f32>,
+    me: bool,
+}
+
+/// Stable color derived from a player name
+fn name_color(name: &str) -> Rgba<f32> {
+    let hash = name.bytes().fold(2166136261u32, |hash, byte| {
+        (hash ^ byte as u32).wrapping_mul(16777619)
+    });
+    Hsla::new((hash % 360) as f32 / 360.0, 0.7, 0.5, 1.0).into()
+}
+
+fn format_time(seconds: f32) -> String {
+    let seconds = seconds as i32;
+    let minutes = seconds / 60;
+    let seconds = seconds % 60;
+    format!("{minutes}:{seconds:02}")
+}
+
+/// Moves a circle so that it does not intersect the box
+fn push_out_of_aabb(pos: vec2<f32>, radius: f32, aabb: Aabb2<f32>) -> vec2<f32> {
+    let closest = vec2(
+        pos.x.clamp(aabb.min.x, aabb.max.x),
+        pos.y.clamp(aabb.min.y, aabb.max.y),
+    );
+    let delta = pos - closest;
+    if delta.len() > 1e-3 {
+        if delta.len() < radius {
+            return closest + delta.normalize() * radius;
+        }
+        return pos;
+    }
+    // center is inside, push out through the closest side
+    let exits = [
+        vec2(aabb.min.x - radius - pos.x, 0.0),
+        vec2(aabb.max.x + radius - pos.x, 0.0),
+        vec2(0.0, aabb.min.y - radius - pos.y),
+        vec2(0.0, aabb.max.y + radius - pos.y),
+    ];
+    pos + exits
+        .into_iter()
+        .min_by_key(|exit| r32(exit.len()))
+        .unwrap()
 }
 
 struct Game {
-    music: Option<geng::SoundEffect>,
-    spectating: bool,
-    edit_name: bool,
+    music: geng::SoundEffect,
+    settings: Settings,
+    /// Settings slider held with the mouse
+    dragging_slider: Option<Slider>,
+    spectator: Option<Spectator>,
+    lobby_filter: String,
+    /// Column and whether it is sorted descending
+    lobby_sort: (LobbySort, bool),
+    chat: VecDeque<ChatLine>,
+    chat_input: String,
+    /// Limit set by the server
+    max_chat_length: usize,
+    /// Our emote and when it was sent
+    emote: Option<(Emote, Timer)>,
+    muted: HashSet<ClientId>,
+    reported: HashSet<ClientId>,
+    /// Why the server stopped talking to us
+    disconnected: Option<String>,
     name_updated: bool,
     name: String,
     dbg: Option<vec2<f32>>,
@@ -220,9 +480,26 @@ struct Game {
     my_id: ClientId,
     geng: Geng,
     assets: Rc<Assets>,
+    asset_watcher: Option<AssetWatcher>,
     baby: Option<Baby>,
     host_race: bool,
+    lanes: bool,
     join_race: Option<ClientId>,
+    race_id: Option<RaceId>,
+    lane: Option<Lane>,
+    track: Option<Track>,
+    tracks: Vec<String>,
+    hosted_track: Option<String>,
+    /// Whether the server has our custom track, so it can be picked again when hosting
+    custom_track_uploaded: bool,
+    /// Why the uploaded custom track was rejected
+    track_error: Option<String>,
+    editor: Option<Editor>,
+    /// Index into all checkpoints of the current track
+    next_checkpoint: usize,
+    progress: f32,
+    /// Our finish time in the current race as seen by the server
+    race_finish_time: Option<f32>,
     other_babis: HashMap<ClientId, Baby>,
     others: BTreeMap<ClientId, ClientServerState>,
     camera: Camera2d,
@@ -230,24 +507,63 @@ struct Game {
     timer: Timer,
     framebuffer_size: vec2<f32>,
     prev_cursor_pos: vec2<f32>,
+    /// Whether the server is waiting for our state
+    sync_pending: bool,
+    /// Minimum time between our syncs, set while spectating
+    sync_interval: f32,
+    sync_timer: Timer,
     connection: Connection,
     locked_limb: Option<Limb>,
+    control_scheme: ControlScheme,
+    /// Limb selected with the keyboard scheme
+    keyboard_limb: Limb,
+    /// Fingers on the screen in the order they were put down
+    touches: Vec<TouchPoint>,
+    bindings: Bindings,
+    gamepads: Gamepads,
+    /// Action and binding slot waiting for a key or button to be bound to
+    rebinding: Option<(Action, usize)>,
+    menu_screen: MenuScreen,
+    ui: Ui<MenuItemAction>,
 }
 
 type Connection = geng::net::client::Connection<ServerMessage, ClientMessage>;
 
 impl Game {
-    pub async fn new(geng: &Geng, assets: &Rc<Assets>, mut connection: Connection) -> Self {
-        let ServerMessage::Auth { id: my_id } = connection.next().await.unwrap().unwrap() else {
+    pub async fn new(
+        geng: &Geng,
+        assets: &Rc<Assets>,
+        mut connection: Connection,
+        dev: bool,
+    ) -> Self {
+        let ServerMessage::Auth {
+            id: my_id,
+            max_chat_length,
+        } = connection.next().await.unwrap().unwrap()
+        else {
             unreachable!()
         };
-        let music = Some(assets.music.play());
+        let settings = Settings::load();
+        let mut music = assets.music.effect(geng.audio().default_type());
+        music.set_volume(settings.music_volume);
+        music.play();
+        geng.window().set_fullscreen(settings.fullscreen);
         Self {
             other_babis: default(),
             music,
-            spectating: false,
+            settings,
+            dragging_slider: None,
+            spectator: None,
             name_updated: true,
-            edit_name: false,
+            lobby_filter: String::new(),
+            lobby_sort: (LobbySort::Host, false),
+            chat: VecDeque::new(),
+            chat_input: String::new(),
+            max_chat_length,
+            emote: None,
+            muted: HashSet::new(),
+            reported: HashSet::new(),
+            disconnected: None,
             name: preferences::load("name").unwrap_or("baby".to_owned()),
             finish_time: 0.0,
             hovered_limb: Limb::LeftArm,
@@ -262,9 +578,22 @@ impl Game {
             others: default(),
             join_race: None,
             host_race: false,
+            lanes: false,
+            race_id: None,
+            lane: None,
+            track: None,
+            tracks: Vec::new(),
+            hosted_track: None,
+            custom_track_uploaded: false,
+            track_error: None,
+            editor: None,
+            next_checkpoint: 0,
+            progress: 0.0,
+            race_finish_time: None,
             connection,
             geng: geng.clone(),
             assets: assets.clone(),
+            asset_watcher: dev.then(AssetWatcher::new),
             baby: None,
             camera: Camera2d {
                 center: vec2::ZERO,
@@ -274,7 +603,18 @@ impl Game {
             timer: Timer::new(),
             framebuffer_size: vec2::splat(1.0),
             prev_cursor_pos: vec2::ZERO,
+            sync_pending: false,
+            sync_interval: 0.0,
+            sync_timer: Timer::new(),
             locked_limb: None,
+            control_scheme: preferences::load("control_scheme").unwrap_or(ControlScheme::Mouse),
+            keyboard_limb: Limb::LeftArm,
+            touches: Vec::new(),
+            bindings: Bindings::load(),
+            gamepads: Gamepads::new(),
+            rebinding: None,
+            menu_screen: MenuScreen::Main,
+            ui: default(),
             dbg: None,
         }
     }
@@ -345,35 +685,68 @@ impl Game {
         }
     }
 
-    fn baby_control(&mut self, cursor_pos: vec2<f32>) {
-        let Some(baby) = &mut self.baby else {
+    fn baby_control(&mut self, input: ControlInput, delta_time: f32) {
+        let track = match &self.editor {
+            Some(editor) => Some(&editor.track),
+            None => self.track.as_ref(),
+        };
+        let (Some(baby), Some(track)) = (&mut self.baby, track) else {
             self.locked_limb = None;
             self.locked_ground_pos = None;
             return;
         };
-        if baby.pos.y < 1.0 {
-            baby.pos.y = 1.0;
+        let checkpoints = track.all_checkpoints();
+        let passed = match self.next_checkpoint {
+            0 => 0.0,
+            next => checkpoints[next - 1],
+        };
+        let projection = track.project(baby.pos, passed);
+        let (center, half_width) = match &self.lane {
+            Some(lane) => (lane.center, lane.width / 2.0),
+            None => (0.0, track.width / 2.0),
+        };
+        let max_offset = (half_width - baby.radius).max(0.0);
+        let constrained = TrackPoint {
+            progress: projection.progress.max(1.0),
+            offset: projection
+                .offset
+                .clamp(center - max_offset, center + max_offset),
+        };
+        if constrained.progress != projection.progress || constrained.offset != projection.offset {
+            baby.pos = track.unproject(constrained);
         }
-        if baby.pos.y > self.assets.config.track_len - 1.0 {
-            self.baby = None;
-            self.assets.win.play();
-            self.connection.send(ClientMessage::Finish);
-            return;
+        self.progress = constrained.progress;
+        if let Some(&checkpoint) = checkpoints.get(self.next_checkpoint) {
+            if self.progress >= checkpoint {
+                self.next_checkpoint += 1;
+            }
         }
-        for (&id, other) in &self.others {
-            if id == self.my_id {
-                continue;
+        let mut speed: f32 = 1.0;
+        for obstacle in &track.obstacles {
+            let config = &self.assets.config.obstacles[&obstacle.kind];
+            let aabb = obstacle.aabb();
+            if config.solid {
+                baby.pos = push_out_of_aabb(baby.pos, baby.radius, aabb);
+            } else if aabb.contains(baby.pos) {
+                speed = speed.min(config.speed);
+                let slide = vec2(1.0, 0.0).rotate(Angle::from_degrees(obstacle.angle))
+                    * config.slide
+                    * delta_time;
+                baby.pos += slide;
+                if let Some(pos) = &mut self.locked_ground_pos {
+                    *pos += slide;
+                }
             }
-            let Some(other) = &other.baby else {
-                continue;
-            };
-            let delta_pos = other.pos - baby.pos;
-            let penetration = baby.radius + other.radius - delta_pos.len();
-            if delta_pos.len() > 1e-3 && penetration > 0.0 {
-                baby.pos -= delta_pos.normalize() * penetration;
+        }
+        if self.next_checkpoint >= checkpoints.len() {
+            self.baby = None;
+            self.play_sfx(&self.assets.win);
+            if self.editor.is_none() {
+                self.connection.send(ClientMessage::Finish);
             }
+            return;
         }
-        baby.head_rotation = (((cursor_pos - (baby.pos + self.assets.config.baby.head_offset))
+        baby.head_rotation = (((input.aim - (baby.pos + self.assets.config.baby.head_offset))
             .arg()
             - baby.rotation
             - Angle::from_degrees(90.0))
@@ -382,24 +755,20 @@ impl Game {
             .clamp_abs(Angle::from_degrees(
                 self.assets.config.baby.max_head_rotation,
             ));
-        let delta = (cursor_pos - self.prev_cursor_pos) * self.assets.config.sensitivity;
-        let air_control = self
-            .geng
-            .window()
-            .is_button_pressed(geng::MouseButton::Right);
-        let ground_control = self
-            .geng
-            .window()
-            .is_button_pressed(geng::MouseButton::Left);
-        let angle = (cursor_pos - baby.pos).arg();
-        let hovered = Limb::all()
-            .min_by_key(|limb| {
-                (angle - baby.rotation - baby.limbs[limb].angle)
-                    .normalized_pi()
-                    .abs()
-                    .map(r32)
-            })
-            .unwrap();
+        let delta = input.delta * speed;
+        let air_control = input.air;
+        let ground_control = input.ground;
+        let angle = (input.aim - baby.pos).arg();
+        let hovered = input.limb.unwrap_or_else(|| {
+            Limb::all()
+                .min_by_key(|limb| {
+                    (angle - baby.rotation - baby.limbs[limb].angle)
+                        .normalized_pi()
+                        .abs()
+                        .map(r32)
+                })
+                .unwrap()
+        });
         if air_control || ground_control {
             let limb = match self.locked_limb {
                 Some(limb) => limb,
@@ -474,6 +843,317 @@ impl Game {
             self.hovered_limb = hovered;
         }
     }
+    fn current_track(&self) -> Option<&Track> {
+        match &self.editor {
+            Some(editor) => Some(&editor.track),
+            None => self.track.as_ref(),
+        }
+    }
+    fn theme(&self, track: &Track) -> &ThemeConfig {
+        let themes = &self.assets.config.themes;
+        themes
+            .get(&track.theme)
+            .unwrap_or(&themes[&self.assets.config.default_theme])
+    }
+
+    /// Draws a texture along the segment, repeating it every `scale` world units
+    fn draw_strip(
+        &self,
+        framebuffer: &mut ugli::Framebuffer,
+        texture: &ugli::Texture,
+        segment: TrackSegment,
+        half_width: f32,
+        scale: f32,
+        color: Rgba<f32>,
+    ) {
+        let aspect = texture.size().map(|x| x as f32).aspect();
+        self.geng.draw2d().draw_textured(
+            framebuffer,
+            &self.camera,
+            &[(0.0, -1.0), (0.0, 1.0), (1.0, 1.0), (1.0, -1.0)].map(|(t, side)| {
+                let along = segment.len() * t;
+                let offset = side * half_width;
+                draw2d::TexturedVertex {
+                    a_pos: segment.start + segment.direction() * along + segment.normal() * offset,
+                    a_color: Rgba::WHITE,
+                    a_vt: vec2(offset / scale / aspect, (segment.progress + along) / scale),
+                }
+            }),
+            texture,
+            color,
+            ugli::DrawMode::TriangleFan,
+        );
+    }
+
+    /// Draws a line following the center line of the track at given offset
+    fn draw_track_line(
+        &self,
+        framebuffer: &mut ugli::Framebuffer,
+        segments: &[TrackSegment],
+        offset: f32,
+        width: f32,
+        color: Rgba<f32>,
+    ) {
+        for segment in segments {
+            let normal = segment.normal();
+            self.geng.draw2d().draw2d(
+                framebuffer,
+                &self.camera,
+                &draw2d::Polygon::new(
+                    vec![
+                        segment.start + normal * (offset - width / 2.0),
+                        segment.start + normal * (offset + width / 2.0),
+                        segment.end + normal * (offset + width / 2.0),
+                        segment.end + normal * (offset - width / 2.0),
+                    ],
+                    color,
+                ),
+            );
+        }
+    }
+
+    /// Draws a line across the track at given progress
+    fn draw_track_crossing(
+        &self,
+        framebuffer: &mut ugli::Framebuffer,
+        track: &Track,
+        progress: f32,
+        color: Rgba<f32>,
+    ) {
+        let half_line_width = self.assets.config.finish_line_width / 2.0;
+        let vertices = [(-1.0, -1.0), (-1.0, 1.0), (1.0, 1.0), (1.0, -1.0)].map(|(along, side)| {
+            track.unproject(TrackPoint {
+                progress: progress + along * half_line_width,
+                offset: side * track.width / 2.0,
+            })
+        });
+        self.geng.draw2d().draw2d(
+            framebuffer,
+            &self.camera,
+            &draw2d::Polygon::new(vertices.to_vec(), color),
+        );
+    }
+
+    fn draw_track(&self, framebuffer: &mut ugli::Framebuffer, track: &Track) {
+        let segments = track.segments();
+        let total_length = track.total_length();
+        let track_half_width = track.width / 2.0;
+        for &segment in segments {
+            self.draw_strip(
+                framebuffer,
+                &self.assets.ruler,
+                segment,
+                track_half_width,
+                total_length,
+                self.theme(track).ruler_color,
+            );
+        }
+        for side in [-1.0, 1.0] {
+            self.draw_track_line(
+                framebuffer,
+                segments,
+                side * track_half_width,
+                self.assets.config.wall_width,
+                self.assets.config.wall_color,
+            );
+        }
+        for decoration in &track.decorations {
+            self.geng.draw2d().quad(
+                framebuffer,
+                &self.camera,
+                decoration.aabb(),
+                self.assets.config.decorations[&decoration.kind].color,
+            );
+        }
+        let checkpoints = track.all_checkpoints();
+        for (index, &checkpoint) in checkpoints.iter().enumerate() {
+            let color = if index + 1 == checkpoints.len() {
+                self.assets.config.finish_line_color
+            } else if index == self.next_checkpoint && self.baby.is_some() {
+                self.assets.config.next_checkpoint_color
+            } else {
+                self.assets.config.checkpoint_color
+            };
+            self.draw_track_crossing(framebuffer, track, checkpoint, color);
+        }
+        for obstacle in &track.obstacles {
+            self.geng.draw2d().quad(
+                framebuffer,
+                &self.camera,
+                obstacle.aabb(),
+                self.assets.config.obstacles[&obstacle.kind].color,
+            );
+        }
+        let parents_half_width = self.assets.config.camera.fov * 2.0;
+        if let (Some(first), Some(last)) = (segments.first(), segments.last()) {
+            let start = TrackSegment {
+                start: first.start - first.direction() * track.parents_height,
+                end: first.start,
+                progress: -track.parents_height,
+            };
+            let end = TrackSegment {
+                start: last.end,
+                end: last.end + last.direction() * track.parents_height,
+                progress: 0.0,
+            };
+            for segment in [start, end] {
+                self.draw_strip(
+                    framebuffer,
+                    &self.assets.parents,
+                    segment,
+                    parents_half_width,
+                    track.parents_height,
+                    Rgba::WHITE,
+                );
+            }
+        }
+    }
+
+    /// Participants of our race, leader first
+    fn standings(&self) -> Vec<Standing> {
+        let Some(race_id) = self.watched_race().or(self.race_id) else {
+            return Vec::new();
+        };
+        let mut standings: Vec<Standing> = self
+            .others
+            .values()
+            .filter(|other| other.race_id == Some(race_id))
+            .map(|other| Standing {
+                name: &other.name,
+                progress: other.progress,
+                finish_time: other.finish_time,
+                me: false,
+            })
+            .collect();
+        if self.race_id == Some(race_id) {
+            standings.push(Standing {
+                name: &self.name,
+                progress: self.progress,
+                finish_time: self.race_finish_time,
+                me: true,
+            });
+        }
+        standings.sort_by_key(|standing| match standing.finish_time {
+            Some(time) => (0, r32(time)),
+            None => (1, r32(-standing.progress)),
+        });
+        standings
+    }
+    fn draw_standings(&self, framebuffer: &mut ugli::Framebuffer) {
+        let standings = self.standings();
+        if standings.is_empty() {
+            return;
+        }
+        // Finished racers stop syncing their progress, so they do not count
+        let leader_progress = standings
+            .iter()
+            .filter(|standing| standing.finish_time.is_none())
+            .map(|standing| standing.progress)
+            .max_by_key(|&progress| r32(progress))
+            .unwrap_or(0.0);
+
+        let config = &self.assets.config.ui;
+        let top_left = self
+            .ui_camera
+            .view_area(self.framebuffer_size)
+            .bounding_box()
+            .top_left();
+        self.geng.draw2d().quad(
+            framebuffer,
+            &self.ui_camera,
+            Aabb2::point(top_left)
+                .extend_right(config.standings_size * 10.0)
+                .extend_down(config.standings_size * (standings.len() as f32 + 0.5)),
+            config.standings_bg_color,
+        );
+        for (index, standing) in standings.iter().enumerate() {
+            let status = match standing.finish_time {
+                Some(time) => format_time(time),
+                None if standing.progress >= leader_progress => "leader".to_owned(),
+                None => format!("-{:.1}", leader_progress - standing.progress),
+            };
+            self.geng.default_font().draw(
+                framebuffer,
+                &self.ui_camera,
+                &format!("{}. {} {status}", index + 1, standing.name),
+                vec2(geng::TextAlign::LEFT, geng::TextAlign::TOP),
+                mat3::translate(
+                    top_left
+                        + vec2(
+                            config.standings_size * 0.25,
+                            -(index as f32 + 0.25) * config.standings_size,
+                        ),
+                ) * mat3::scale_uniform(config.standings_size),
+                if standing.me {
+                    config.standings_highlight_color
+                } else {
+                    config.standings_color
+                },
+            );
+        }
+    }
+
+    fn draw_progress_strip(&self, framebuffer: &mut ugli::Framebuffer) {
+        let Some(track) = &self.track else {
+            return;
+        };
+        let standings = self.standings();
+        if standings.is_empty() {
+            return;
+        }
+        let config = &self.assets.config.ui;
+        let view = self
+            .ui_camera
+            .view_area(self.framebuffer_size)
+            .bounding_box();
+        let x = view.max.x - config.progress_strip_margin;
+        let bottom = view.min.y + config.progress_strip_margin;
+        let top = view.max.y - config.progress_strip_margin;
+        let y = |progress: f32| bottom + (top - bottom) * (progress / track.finish).clamp(0.0, 1.0);
+        self.geng.draw2d().quad(
+            framebuffer,
+            &self.ui_camera,
+            Aabb2::point(vec2(x, bottom))
+                .extend_symmetric(vec2(config.progress_strip_width / 2.0, 0.0))
+                .extend_up(top - bottom),
+            config.progress_strip_color,
+        );
+        for progress in track.all_checkpoints() {
+            self.geng.draw2d().quad(
+                framebuffer,
+                &self.ui_camera,
+                Aabb2::point(vec2(x, y(progress)))
+                    .extend_symmetric(vec2(config.progress_strip_width, 0.05)),
+                self.assets.config.checkpoint_color,
+            );
+        }
+        // Draw leader last so that it is on top
+        for standing in standings.iter().rev() {
+            let progress = match standing.finish_time {
+                Some(_) => track.finish,
+                None => standing.progress,
+            };
+            let pos = vec2(x, y(progress));
+            let radius = config.progress_marker_radius;
+            if standing.me {
+                self.geng.draw2d().circle(
+                    framebuffer,
+                    &self.ui_camera,
+                    pos,
+                    radius * 1.5,
+                    config.standings_highlight_color,
+                );
+            }
+            self.geng.draw2d().circle(
+                framebuffer,
+                &self.ui_camera,
+                pos,
+                radius,
+                name_color(standing.name),
+            );
+        }
+    }
+
     fn handler_multiplayer(&mut self) {
         let new_messages: Vec<_> = self.connection.new_messages().collect();
         for message in new_messages {
@@ -484,28 +1164,97 @@ impl Game {
                     self.rank = Some(rank);
                     self.finish_time = time;
                 }
+                ServerMessage::Bump(delta) => {
+                    if let Some(baby) = &mut self.baby {
+                        baby.pos += delta;
+                    }
+                    if let Some(pos) = &mut self.locked_ground_pos {
+                        *pos += delta;
+                    }
+                }
+                ServerMessage::TrackList(tracks) => self.tracks = tracks,
+                ServerMessage::Track(track) => self.track = Some(track),
+                ServerMessage::TrackRejected(reason) => {
+                    self.track_error = Some(reason);
+                    self.hosted_track = None;
+                    self.custom_track_uploaded = false;
+                }
                 ServerMessage::Spawn(pos) => {
                     self.baby = Some(Baby::new(Some(&self.assets), pos));
-                    self.assets.start.play();
+                    self.next_checkpoint = 0;
+                    self.progress = 0.0;
+                    self.play_sfx(&self.assets.start);
                     self.host_race = false;
                     self.timer.reset();
                 }
-                ServerMessage::StateSync { clients } => {
-                    self.others = clients
-                        .into_iter()
-                        .filter(|&(id, _)| id != self.my_id)
-                        .collect();
-                    self.connection.send(ClientMessage::StateSync(ClientState {
-                        baby: self.baby.clone(),
-                        host_race: self.host_race,
-                        join_race: self.join_race,
-                    }));
+                ServerMessage::StateSync { mut clients } => {
+                    if let Some(me) = clients.remove(&self.my_id) {
+                        self.race_id = me.race_id;
+                        self.lane = me.lane;
+                        self.race_finish_time = me.finish_time;
+                    }
+                    self.others = clients;
+                    self.sync_pending = true;
+                }
+                ServerMessage::Spectate {
+                    race,
+                    sync_interval,
+                } => {
+                    self.sync_interval = sync_interval;
+                    // A timer sync may have been dropped by the server, so one is always sent
+                    // to restart the ping-pong
+                    self.sync_pending = true;
+                    if let Some(spectator) = &mut self.spectator {
+                        spectator.race = race;
+                    }
+                }
+                ServerMessage::Chat { from, name, text } => {
+                    if !from.is_some_and(|id| self.is_muted(id)) {
+                        self.receive_chat(name, text);
+                    }
+                }
+                ServerMessage::Banned => {
+                    self.disconnected = Some("you are banned from this server".to_owned());
+                }
+                ServerMessage::Kicked => {
+                    self.baby = None;
+                    self.disconnected = Some("you were kicked from the server".to_owned());
+                }
+                ServerMessage::RaceEnded => {
+                    if self.baby.take().is_some() {
+                        self.play_sfx(&self.assets.stop);
+                    }
+                    self.receive_chat(
+                        "server".to_owned(),
+                        "the race was ended by an admin".to_owned(),
+                    );
                 }
                 ServerMessage::Auth { .. } => unreachable!(),
             }
         }
     }
 
+    fn send_sync(&mut self) {
+        // Spectators sync on a timer instead of answering each state,
+        // the server ignores their syncs that come too often
+        let throttled = self.sync_interval > 0.0;
+        if !(self.sync_pending || throttled)
+            || self.sync_timer.elapsed().as_secs_f64() < self.sync_interval as f64
+        {
+            return;
+        }
+        self.sync_pending = false;
+        self.sync_timer.reset();
+        self.connection.send(ClientMessage::StateSync(ClientState {
+            baby: self.baby.clone(),
+            host_race: self.host_race,
+            lanes: self.lanes,
+            track: self.hosted_track.clone(),
+            progress: self.progress,
+            join_race: self.join_race,
+        }));
+    }
+
     fn interpolate(&mut self, delta_time: f32) {
         let updates: HashMap<ClientId, Baby> = self
             .others
@@ -558,6 +1307,14 @@ impl Game {
     }
 }
 
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+enum MenuScreen {
+    Main,
+    Settings,
+    Bindings,
+    Players,
+}
+
 #[derive(Copy, Clone, PartialEq, Eq, Hash)]
 enum MenuItemAction {
     StartRace,
@@ -566,192 +1323,337 @@ enum MenuItemAction {
     Join(ClientId),
     EditName,
     ToggleSpectating,
-    ToggleMusic,
+    Watch(Option<RaceId>),
+    DragSlider(Slider),
+    ToggleFullscreen,
+    NextControlScheme,
+    OpenScreen(MenuScreen),
+    Rebind(Action, usize),
+    ResetBindings,
+    ToggleLanes,
+    NextTrack,
+    OpenEditor,
+    EditFilter,
+    SortLobby(LobbySort),
+    ToggleMute(ClientId),
+    Report(ClientId),
 }
 
-struct MenuItem {
-    text: String,
-    action: Option<MenuItemAction>,
-}
+type MenuWidget = Widget<MenuItemAction>;
 
 impl Game {
-    fn menu(&self) -> Vec<(f32, MenuItem)> {
-        let mut items = vec![MenuItem {
-            text: format!("your name: {}", self.name),
-            action: Some(MenuItemAction::EditName),
-        }];
-        items.push(MenuItem {
-            text: if self.music.is_some() {
-                "turn off music"
+    fn menu(&self) -> Layout<MenuItemAction> {
+        let view = self
+            .ui_camera
+            .view_area(self.framebuffer_size)
+            .bounding_box();
+        let mut layout = Layout::new(view.min.x, view.max.x);
+        let top = self.assets.config.ui.fov / 2.0;
+        if let Some(reason) = &self.disconnected {
+            layout.column(1.0, vec![Widget::Label(reason.clone())], &self.ui);
+            return layout;
+        }
+        match self.menu_screen {
+            MenuScreen::Main => {}
+            MenuScreen::Settings => {
+                layout.column(1.0, self.settings_menu(), &self.ui);
+                return layout;
+            }
+            MenuScreen::Bindings => {
+                layout.column(top - 1.0, self.bindings_menu(), &self.ui);
+                return layout;
+            }
+            MenuScreen::Players => {
+                layout.column(1.0, self.players_menu(), &self.ui);
+                return layout;
+            }
+        }
+        let spectate = Widget::Button(
+            if self.spectator.is_some() {
+                "stop spectating"
             } else {
-                "turn on music"
+                "spectate"
             }
             .to_owned(),
-            action: Some(MenuItemAction::ToggleMusic),
-        });
+            MenuItemAction::ToggleSpectating,
+        );
+        if let Some(spectator) = &self.spectator {
+            if spectator.race.is_some() {
+                layout.column(
+                    top,
+                    vec![
+                        spectate,
+                        Widget::Button("other races".to_owned(), MenuItemAction::Watch(None)),
+                    ],
+                    &self.ui,
+                );
+                return layout;
+            }
+            layout.column(top, vec![spectate], &self.ui);
+            let races = self.active_races();
+            let items = races
+                .iter()
+                .map(|(&race_id, racers)| {
+                    let names: Vec<&str> = racers
+                        .iter()
+                        .map(|id| self.others[id].name.as_str())
+                        .collect();
+                    Widget::Button(names.join(", "), MenuItemAction::Watch(Some(race_id)))
+                })
+                .collect();
+            layout.column(
+                1.0,
+                vec![
+                    Widget::Label(
+                        if races.is_empty() {
+                            "no races to watch"
+                        } else {
+                            "watch race:"
+                        }
+                        .to_owned(),
+                    ),
+                    Widget::List {
+                        id: "watch",
+                        rows: 8,
+                        items,
+                    },
+                ],
+                &self.ui,
+            );
+            return layout;
+        }
+        layout.column(top, vec![spectate], &self.ui);
+
+        let mut items: Vec<MenuWidget> = vec![
+            Widget::TextInput {
+                id: "name",
+                text: format!("your name: {}", self.name),
+                action: MenuItemAction::EditName,
+            },
+            Widget::Row(vec![
+                Widget::Button(
+                    "settings".to_owned(),
+                    MenuItemAction::OpenScreen(MenuScreen::Settings),
+                ),
+                Widget::Button(
+                    format!("controls: {}", self.control_scheme.name()),
+                    MenuItemAction::NextControlScheme,
+                ),
+                Widget::Button(
+                    "key bindings".to_owned(),
+                    MenuItemAction::OpenScreen(MenuScreen::Bindings),
+                ),
+                Widget::Button(
+                    "players".to_owned(),
+                    MenuItemAction::OpenScreen(MenuScreen::Players),
+                ),
+            ]),
+        ];
         if self.host_race {
             items.extend([
-                MenuItem {
-                    text: "Start!".to_owned(),
-                    action: Some(MenuItemAction::StartRace),
-                },
-                MenuItem {
-                    text: "cancel".to_owned(),
-                    action: Some(MenuItemAction::Cancel),
-                },
-                MenuItem {
-                    text: "joined people:".to_owned(),
-                    action: None,
-                },
-                MenuItem {
-                    text: "YOU".to_owned(),
-                    action: None,
-                },
+                Widget::Row(vec![
+                    Widget::Button("Start!".to_owned(), MenuItemAction::StartRace),
+                    Widget::Button("cancel".to_owned(), MenuItemAction::Cancel),
+                ]),
+                Widget::Row(vec![
+                    Widget::Button(
+                        if self.lanes {
+                            "lanes: on"
+                        } else {
+                            "lanes: off"
+                        }
+                        .to_owned(),
+                        MenuItemAction::ToggleLanes,
+                    ),
+                    Widget::Button(
+                        format!(
+                            "track: {}",
+                            self.hosted_track.as_deref().unwrap_or("default")
+                        ),
+                        MenuItemAction::NextTrack,
+                    ),
+                ]),
             ]);
+            if let Some(reason) = &self.track_error {
+                items.push(Widget::Label(format!("custom track rejected: {reason}")));
+            }
+            let mut joined = vec![Widget::Label("YOU".to_owned())];
             for (&id, client) in &self.others {
                 if id == self.my_id {
                     continue;
                 }
                 if client.joined == Some(self.my_id) {
-                    items.push(MenuItem {
-                        text: client.name.clone(),
-                        action: None,
-                    });
+                    joined.push(Widget::Label(client.name.clone()));
                 }
             }
-        } else if let Some(joined) = self.join_race {
             items.extend([
-                MenuItem {
-                    text: "wait for the race to start".to_owned(),
-                    action: None,
-                },
-                MenuItem {
-                    text: "leave".to_owned(),
-                    action: Some(MenuItemAction::Cancel),
-                },
-                MenuItem {
-                    text: "joined people:".to_owned(),
-                    action: None,
-                },
-                MenuItem {
-                    text: "YOU".to_owned(),
-                    action: None,
+                Widget::Label("joined people:".to_owned()),
+                Widget::List {
+                    id: "joined",
+                    rows: 5,
+                    items: joined,
                 },
             ]);
+        } else if let Some(joined) = self.join_race {
+            if let Some(host) = self.others.get(&joined) {
+                items.push(Widget::Label(format!("track: {}", host.track)));
+                if host.lanes {
+                    items.push(Widget::Label("racing in lanes".to_owned()));
+                }
+            }
+            let mut people = vec![Widget::Label("YOU".to_owned())];
             for (&id, client) in &self.others {
                 if id == self.my_id {
                     continue;
                 }
                 if client.joined == Some(joined) || id == joined {
-                    items.push(MenuItem {
-                        text: client.name.clone(),
-                        action: None,
-                    });
+                    people.push(Widget::Label(client.name.clone()));
                 }
             }
-        } else {
             items.extend([
-                MenuItem {
-                    text: "Start SOLO!".to_owned(),
-                    action: Some(MenuItemAction::StartRace),
-                },
-                MenuItem {
-                    text: "Host a race".to_owned(),
-                    action: Some(MenuItemAction::Host),
-                },
-                MenuItem {
-                    text: "join race:".to_owned(),
-                    action: None,
+                Widget::Label("wait for the race to start".to_owned()),
+                Widget::Button("leave".to_owned(), MenuItemAction::Cancel),
+                Widget::Label("joined people:".to_owned()),
+                Widget::List {
+                    id: "joined",
+                    rows: 4,
+                    items: people,
                 },
             ]);
-            for (&id, client) in &self.others {
-                if id == self.my_id {
-                    continue;
-                }
-                if client.hosting_race {
-                    items.push(MenuItem {
-                        text: client.name.clone(),
-                        action: Some(MenuItemAction::Join(id)),
-                    });
-                }
-            }
-        }
-        let mut result = Vec::new();
-        let mut y = 0.0;
-        for item in items {
-            result.push((y, item));
-            y -= 1.0;
-        }
-        let spectate = (
-            self.assets.config.ui.fov / 2.0 - 1.0,
-            MenuItem {
-                text: if self.spectating {
-                    "stop spectating"
-                } else {
-                    "spectate"
-                }
-                .to_owned(),
-                action: Some(MenuItemAction::ToggleSpectating),
-            },
-        );
-        if self.spectating {
-            return vec![spectate];
         } else {
-            result.push(spectate);
+            items.push(Widget::Row(vec![
+                Widget::Button("Start SOLO!".to_owned(), MenuItemAction::StartRace),
+                Widget::Button("Host a race".to_owned(), MenuItemAction::Host),
+                Widget::Button("Track editor".to_owned(), MenuItemAction::OpenEditor),
+            ]));
+            items.extend(self.lobby_browser());
         }
-        result
+        layout.column(1.0, items, &self.ui);
+        layout
     }
 
-    fn click_menu(&mut self) {
-        if self.baby.is_some() {
-            return;
+    fn ui_cursor(&self, screen_pos: vec2<f32>) -> vec2<f32> {
+        self.ui_camera
+            .screen_to_world(self.framebuffer_size, screen_pos)
+    }
+
+    /// Returns whether a menu item was clicked
+    fn click_menu(&mut self, screen_pos: vec2<f32>) -> bool {
+        if self.baby.is_some() || self.editor.is_some() {
+            return false;
         }
-        let cursor = self.ui_camera.screen_to_world(
-            self.framebuffer_size,
-            self.geng
-                .window()
-                .cursor_position()
-                .unwrap_or(vec2::ZERO)
-                .map(|x| x as f32),
-        );
-        for (y, item) in self.menu() {
-            let hovered = cursor.y > y && cursor.y < y + 1.0;
-            if hovered {
-                if let Some(action) = item.action {
-                    self.perform_menu_action(action);
-                    if !matches!(action, MenuItemAction::EditName) {
-                        self.geng.window().stop_text_edit();
-                    }
-                    return;
-                }
+        if let Some(action) = self.ui.hit(self.ui_cursor(screen_pos)) {
+            if !matches!(
+                action,
+                MenuItemAction::EditName | MenuItemAction::EditFilter
+            ) {
+                self.set_focus(None);
             }
+            self.perform_menu_action(action);
+            self.update_menu();
+            return true;
         }
-        self.geng.window().stop_text_edit();
+        self.set_focus(None);
+        false
     }
 
+    /// Lays the menu out for drawing and input until the next update
+    fn update_menu(&mut self) {
+        let layout = self.menu();
+        self.ui.set_layout(layout);
+    }
+
+    /// Gives the keyboard to a text input, or takes it away with `None`.
+    /// The name is saved and sent once it stops being edited
+    fn set_focus(&mut self, focus: Option<&'static str>) {
+        if self.ui.is_focused("name") && focus != Some("name") {
+            self.name_updated = true;
+            preferences::save("name", &self.name);
+        }
+        match focus {
+            Some("name") => self.geng.window().start_text_edit(&self.name),
+            Some("filter") => self.geng.window().start_text_edit(&self.lobby_filter),
+            Some(_) => self.geng.window().start_text_edit(""),
+            None => self.geng.window().stop_text_edit(),
+        }
+        self.ui.set_focus(focus);
+    }
+
+    /// Returns whether a menu list was scrolled
+    fn scroll_menu(&mut self, screen_pos: vec2<f32>, delta: f64) -> bool {
+        if self.baby.is_some() || self.editor.is_some() {
+            return false;
+        }
+        let pos = self.ui_cursor(screen_pos);
+        self.ui.scroll(pos, delta)
+    }
     fn perform_menu_action(&mut self, action: MenuItemAction) {
         match action {
-            MenuItemAction::ToggleMusic => {
-                if let Some(mut music) = self.music.take() {
-                    music.fade_out(time::Duration::from_secs_f64(1.0));
-                } else {
-                    let mut music = self.assets.music.effect(self.geng.audio().default_type());
-                    music.fade_in(time::Duration::from_secs_f64(1.0));
-                    music.play();
-                    self.music = Some(music);
+            MenuItemAction::OpenScreen(screen) => {
+                self.menu_screen = screen;
+                self.rebinding = None;
+            }
+            MenuItemAction::Rebind(action, slot) => self.rebinding = Some((action, slot)),
+            MenuItemAction::ResetBindings => {
+                self.bindings = Bindings::default();
+                self.bindings.save();
+            }
+            MenuItemAction::NextControlScheme => {
+                self.control_scheme = self.control_scheme.next();
+                preferences::save("control_scheme", &self.control_scheme);
+            }
+            MenuItemAction::DragSlider(slider) => self.dragging_slider = Some(slider),
+            MenuItemAction::ToggleFullscreen => self.toggle_fullscreen(),
+            MenuItemAction::ToggleSpectating => {
+                self.spectator = match self.spectator {
+                    Some(_) => {
+                        self.connection.send(ClientMessage::Spectate(None));
+                        self.sync_interval = 0.0;
+                        self.sync_pending = true;
+                        None
+                    }
+                    None => Some(Spectator::new()),
+                };
+                self.camera.fov = Camera2dFov::MinSide(self.assets.config.camera.fov);
+            }
+            MenuItemAction::Watch(race) => {
+                if let Some(spectator) = &mut self.spectator {
+                    spectator.race = race;
+                    spectator.target = SpectatorTarget::Overview;
                 }
+                self.connection.send(ClientMessage::Spectate(race));
+                self.camera.fov = Camera2dFov::MinSide(self.assets.config.camera.fov);
+            }
+            MenuItemAction::EditFilter => {
+                self.set_focus((!self.ui.is_focused("filter")).then_some("filter"))
             }
-            MenuItemAction::ToggleSpectating => self.spectating = !self.spectating,
+            MenuItemAction::SortLobby(sort) => self.sort_lobby(sort),
+            MenuItemAction::ToggleMute(id) => self.toggle_mute(id),
+            MenuItemAction::Report(id) => self.report(id),
             MenuItemAction::EditName => {
-                self.edit_name = !self.edit_name;
-                if self.edit_name {
-                    self.geng.window().start_text_edit(&self.name);
-                } else {
-                    self.geng.window().stop_text_edit();
-                }
+                self.set_focus((!self.ui.is_focused("name")).then_some("name"))
             }
             MenuItemAction::StartRace => self.connection.send(ClientMessage::StartRace),
             MenuItemAction::Host => self.host_race = true,
+            MenuItemAction::ToggleLanes => self.lanes = !self.lanes,
+            MenuItemAction::OpenEditor => {
+                self.editor = Some(Editor::new(&self.assets.config.default_theme));
+                self.camera.center = vec2::ZERO;
+            }
+            MenuItemAction::NextTrack => {
+                let mut tracks = self.tracks.clone();
+                if self.custom_track_uploaded {
+                    tracks.push(CUSTOM_TRACK.to_owned());
+                }
+                let next = match &self.hosted_track {
+                    Some(id) => tracks
+                        .iter()
+                        .position(|track| track == id)
+                        .map_or(0, |i| i + 1),
+                    None => 0,
+                };
+                self.hosted_track = tracks.get(next).cloned();
+            }
             MenuItemAction::Cancel => {
                 self.host_race = false;
                 self.join_race = None;
@@ -761,16 +1663,10 @@ impl Game {
     }
 
     fn draw_menu(&self, framebuffer: &mut ugli::Framebuffer) {
-        if self.baby.is_some() {
+        if self.baby.is_some() || self.editor.is_some() {
             return;
         }
-        let _top_right_corner = self
-            .ui_camera
-            .view_area(self.framebuffer_size)
-            .bounding_box()
-            .top_right();
-        let cursor = self.ui_camera.screen_to_world(
-            self.framebuffer_size,
+        let cursor = self.ui_cursor(
             self.geng
                 .window()
                 .cursor_position()
@@ -780,14 +1676,14 @@ impl Game {
         let font: &geng::Font = self.geng.default_font();
 
         if let Some(rank) = self.rank {
-            if !self.spectating {
-                let seconds = self.finish_time as i32;
-                let minutes = seconds / 60;
-                let seconds = seconds % 60;
+            if self.spectator.is_none() {
                 font.draw(
                     framebuffer,
                     &self.ui_camera,
-                    &format!("You placed #{rank} (time = {minutes}:{seconds:02})"),
+                    &format!(
+                        "You placed #{rank} (time = {})",
+                        format_time(self.finish_time)
+                    ),
                     vec2(geng::TextAlign::CENTER, geng::TextAlign::BOTTOM),
                     mat3::translate(vec2(0.0, self.assets.config.ui.rank_offset))
                         * mat3::scale_uniform(self.assets.config.ui.rank_size),
@@ -796,98 +1692,93 @@ impl Game {
             }
         }
 
-        for (y, item) in self.menu() {
-            self.geng.draw2d().quad(
-                framebuffer,
-                &self.ui_camera,
-                Aabb2::point(vec2(0.0, y))
-                    .extend_up(1.0)
-                    .extend_symmetric(vec2(self.assets.config.ui.fov * 2.0, 0.0)),
-                self.assets.config.ui.bg_color,
-            );
-            let hovered = cursor.y > y && cursor.y < y + 1.0;
-            if hovered && item.action.is_some() {
-                self.geng.draw2d().quad(
-                    framebuffer,
-                    &self.ui_camera,
-                    Aabb2::point(vec2(0.0, y))
-                        .extend_up(1.0)
-                        .extend_symmetric(vec2(self.assets.config.ui.fov * 2.0, 0.0)),
-                    self.assets.config.ui.hover_color,
-                );
-            }
-            if item.action == Some(MenuItemAction::EditName) && self.edit_name {
-                self.geng.draw2d().quad(
-                    framebuffer,
-                    &self.ui_camera,
-                    Aabb2::point(vec2(0.0, y))
-                        .extend_up(1.0)
-                        .extend_symmetric(vec2(self.assets.config.ui.fov * 2.0, 0.0)),
-                    self.assets.config.ui.edit_text_color,
-                );
-                let w = font
-                    .measure(
-                        &item.text,
-                        vec2(geng::TextAlign::CENTER, geng::TextAlign::BOTTOM),
-                    )
-                    .unwrap_or(Aabb2::ZERO);
-                if self.timer.elapsed().as_secs_f64().fract() < 0.5 {
-                    font.draw(
-                        framebuffer,
-                        &self.ui_camera,
-                        "|",
-                        vec2(geng::TextAlign::CENTER, geng::TextAlign::BOTTOM),
-                        mat3::translate(vec2(w.max.x, y + self.assets.config.ui.text_offset)),
-                        match item.action {
-                            None => self.assets.config.ui.label_color,
-                            Some(_) => self.assets.config.ui.button_color,
-                        },
-                    );
-                }
-            }
-            font.draw(
-                framebuffer,
-                &self.ui_camera,
-                &item.text,
-                vec2(geng::TextAlign::CENTER, geng::TextAlign::BOTTOM),
-                mat3::translate(vec2(0.0, y + self.assets.config.ui.text_offset)),
-                match item.action {
-                    None => self.assets.config.ui.label_color,
-                    Some(_) => self.assets.config.ui.button_color,
-                },
-            );
-        }
+        self.ui.draw(
+            &self.geng,
+            framebuffer,
+            &self.ui_camera,
+            &self.assets.config.ui,
+            cursor,
+            self.timer.elapsed().as_secs_f64().fract() < 0.5,
+        );
     }
 }
 
 impl geng::State for Game {
     fn handle_event(&mut self, event: geng::Event) {
+        if self.editor.is_some() && self.baby.is_none() {
+            self.handle_editor_event(event);
+            return;
+        }
+        if let Some(binding) = Binding::from_event(&event) {
+            if self.press_binding(binding) {
+                return;
+            }
+        }
+        if !self.geng.window().is_editing_text() {
+            self.handle_spectator_event(&event);
+        }
         match event {
-            geng::Event::KeyPress { key } => {
-                if key == geng::Key::R {
-                    self.baby = None;
-                    self.assets.stop.play();
-                    self.connection.send(ClientMessage::Despawn);
+            geng::Event::KeyPress { key } => match key {
+                geng::Key::Enter | geng::Key::NumpadEnter => {
+                    if self.ui.focus().is_some_and(|id| id != "chat") {
+                        self.set_focus(None);
+                    } else {
+                        self.toggle_chat();
+                    }
                 }
-                if key == geng::Key::Enter
-                    || key == geng::Key::NumpadEnter
-                    || key == geng::Key::Escape
-                {
-                    self.geng.window().stop_text_edit();
+                geng::Key::Escape => {
+                    self.chat_input.clear();
+                    self.set_focus(None);
                 }
-            }
+                _ => {}
+            },
             geng::Event::MousePress {
                 button: geng::MouseButton::Left,
             } => {
-                self.click_menu();
+                let screen_pos = self
+                    .geng
+                    .window()
+                    .cursor_position()
+                    .unwrap_or(vec2::ZERO)
+                    .map(|x| x as f32);
+                if !self.click_menu(screen_pos) {
+                    let cursor = self
+                        .camera
+                        .screen_to_world(self.framebuffer_size, screen_pos);
+                    self.start_spectator_pan(cursor);
+                }
             }
-            geng::Event::EditText(new_text) => {
-                if self.edit_name {
-                    self.name = new_text;
-                } else {
-                    self.geng.window().stop_text_edit();
+            geng::Event::Wheel { delta } => {
+                let screen_pos = self
+                    .geng
+                    .window()
+                    .cursor_position()
+                    .unwrap_or(vec2::ZERO)
+                    .map(|x| x as f32);
+                self.scroll_menu(screen_pos, delta);
+            }
+            geng::Event::TouchStart(touch) => {
+                let pos = touch.position.map(|x| x as f32);
+                if !self.click_menu(pos) {
+                    self.touches.push(TouchPoint {
+                        id: touch.id,
+                        pos,
+                        prev: pos,
+                    });
+                }
+            }
+            geng::Event::TouchMove(touch) => {
+                if let Some(point) = self.touches.iter_mut().find(|point| point.id == touch.id) {
+                    point.pos = touch.position.map(|x| x as f32);
                 }
             }
+            geng::Event::TouchEnd(touch) => self.touches.retain(|point| point.id != touch.id),
+            geng::Event::EditText(new_text) => match self.ui.focus() {
+                Some("name") => self.name = new_text,
+                Some("filter") => self.lobby_filter = new_text,
+                Some("chat") => self.edit_chat_text(new_text),
+                _ => self.set_focus(None),
+            },
             _ => (),
         }
     }
@@ -895,72 +1786,71 @@ impl geng::State for Game {
         self.framebuffer_size = framebuffer.size().map(|x| x as f32);
         ugli::clear(
             framebuffer,
-            Some(self.assets.config.background_color),
+            Some(match self.current_track() {
+                Some(track) => self.theme(track).background_color,
+                None => self.assets.config.background_color,
+            }),
             None,
             None,
         );
-        let stretched = |framebuffer: &mut ugli::Framebuffer,
-                         texture: &ugli::Texture,
-                         at: f32,
-                         height: f32,
-                         color: Rgba<f32>| {
-            self.geng.draw2d().draw_textured(
-                framebuffer,
-                &self.camera,
-                &[(-1, 0), (1, 0), (1, 1), (-1, 1)].map(|(x, y)| {
-                    let world_x =
-                        self.camera.center.x + x as f32 * self.assets.config.camera.fov * 2.0;
-                    draw2d::TexturedVertex {
-                        a_pos: vec2(world_x, at + y as f32 * height),
-                        a_color: Rgba::WHITE,
-                        a_vt: vec2(
-                            world_x / height / texture.size().map(|x| x as f32).aspect(),
-                            y as f32,
-                        ),
-                    }
-                }),
-                texture,
-                color,
-                ugli::DrawMode::TriangleFan,
+        if let Some(track) = self.current_track() {
+            self.draw_track(framebuffer, track);
+        }
+        if let (Some(track), Some(lane), None) = (&self.track, &self.lane, &self.editor) {
+            let lanes = std::iter::once((lane, self.name.as_str())).chain(
+                self.others
+                    .values()
+                    .filter(|other| other.race_id == self.race_id)
+                    .filter_map(|other| Some((other.lane.as_ref()?, other.name.as_str()))),
             );
-        };
-        stretched(
-            framebuffer,
-            &self.assets.ruler,
-            0.0,
-            self.assets.config.track_len,
-            self.assets.config.ruler_color,
-        );
-        stretched(
-            framebuffer,
-            &self.assets.parents,
-            -self.assets.config.parents_height,
-            self.assets.config.parents_height,
-            Rgba::WHITE,
-        );
-        stretched(
-            framebuffer,
-            &self.assets.parents,
-            self.assets.config.track_len,
-            self.assets.config.parents_height,
-            Rgba::WHITE,
-        );
-        for (id, other) in &self.others {
-            if let Some(baby) = self.other_babis.get(id) {
-                self.draw_baby(framebuffer, baby, false);
+            let segments = track.segments();
+            for (lane, name) in lanes {
+                for side in [-1.0, 1.0] {
+                    self.draw_track_line(
+                        framebuffer,
+                        segments,
+                        lane.center + side * lane.width / 2.0,
+                        self.assets.config.lane_line_width,
+                        self.assets.config.lane_color,
+                    );
+                }
                 self.geng.default_font().draw(
                     framebuffer,
                     &self.camera,
-                    &other.name,
+                    name,
                     vec2(geng::TextAlign::CENTER, geng::TextAlign::BOTTOM),
-                    mat3::translate(baby.pos + vec2(0.0, self.assets.config.nametag_offset))
-                        * mat3::scale_uniform(self.assets.config.nametag_size),
+                    mat3::translate(track.unproject(TrackPoint {
+                        progress: self.assets.config.lane_name_offset,
+                        offset: lane.center,
+                    })) * mat3::scale_uniform(self.assets.config.lane_name_size),
                     self.assets.config.nametag_color,
                 );
             }
         }
+        if self.editor.is_none() {
+            for (id, other) in &self.others {
+                if let Some(baby) = self.other_babis.get(id) {
+                    self.draw_baby(framebuffer, baby, false);
+                    self.geng.default_font().draw(
+                        framebuffer,
+                        &self.camera,
+                        &other.name,
+                        vec2(geng::TextAlign::CENTER, geng::TextAlign::BOTTOM),
+                        mat3::translate(baby.pos + vec2(0.0, self.assets.config.nametag_offset))
+                            * mat3::scale_uniform(self.assets.config.nametag_size),
+                        self.assets.config.nametag_color,
+                    );
+                    if let Some(emote) = other.emote.filter(|_| !self.is_muted(*id)) {
+                        self.draw_emote(framebuffer, baby.pos, emote);
+                    }
+                }
+            }
+        }
         if let Some(baby) = &self.baby {
             self.draw_baby(framebuffer, baby, true);
+            if let Some((emote, _)) = self.emote {
+                self.draw_emote(framebuffer, baby.pos, emote);
+            }
             if baby.pos.y < 2.0 {
                 self.geng.draw2d().draw2d(
                     framebuffer,
@@ -984,18 +1874,33 @@ impl geng::State for Game {
                     self.assets.config.outline.ground_color,
                 );
             }
-            let seconds = self.timer.elapsed().as_secs_f64() as i32;
-            let minutes = seconds / 60;
-            let seconds = seconds % 60;
             self.geng.default_font().draw(
                 framebuffer,
                 &self.ui_camera,
-                &format!("{minutes}:{seconds:02}"),
+                &format_time(self.timer.elapsed().as_secs_f64() as f32),
                 vec2(geng::TextAlign::CENTER, geng::TextAlign::TOP),
                 mat3::translate(vec2(0.0, self.assets.config.ui.fov / 2.0))
                     * mat3::scale_uniform(self.assets.config.ui.timer_size),
                 self.assets.config.ui.timer_color,
             );
+            let spectators = self
+                .others
+                .values()
+                .filter(|other| other.spectating.is_some() && other.spectating == self.race_id)
+                .count();
+            if spectators > 0 {
+                self.geng.default_font().draw(
+                    framebuffer,
+                    &self.ui_camera,
+                    &format!("{spectators} watching"),
+                    vec2(geng::TextAlign::CENTER, geng::TextAlign::TOP),
+                    mat3::translate(vec2(
+                        0.0,
+                        self.assets.config.ui.fov / 2.0 - self.assets.config.ui.timer_size,
+                    )) * mat3::scale_uniform(self.assets.config.ui.spectators_size),
+                    self.assets.config.ui.timer_color,
+                );
+            }
         }
         if let Some(pos) = self.dbg {
             self.geng.draw2d().circle(
@@ -1006,15 +1911,27 @@ impl geng::State for Game {
                 self.assets.config.outline.ground_color,
             );
         }
+        // Finished racers keep their race id until the race ends, so they still see the standings
+        if self.editor.is_none()
+            && (self.baby.is_some() || self.race_id.is_some() || self.watched_race().is_some())
+        {
+            self.draw_standings(framebuffer);
+            self.draw_progress_strip(framebuffer);
+        }
+        self.draw_spectator(framebuffer);
+        self.draw_editor(framebuffer);
         self.draw_menu(framebuffer);
+        self.draw_chat(framebuffer);
     }
     fn update(&mut self, delta_time: f64) {
         let delta_time = delta_time as f32;
+        self.update_asset_watcher();
+        self.update_gamepads();
         self.interpolate(delta_time);
-        if !self.geng.window().is_editing_text() && self.edit_name {
-            self.edit_name = false;
-            self.name_updated = true;
-            preferences::save("name", &self.name);
+        self.update_emote();
+        // The window stops editing on its own, e.g. when it loses focus
+        if self.ui.focus().is_some() && !self.geng.window().is_editing_text() {
+            self.set_focus(None);
         }
         if self.name_updated {
             self.name_updated = false;
@@ -1031,23 +1948,34 @@ impl geng::State for Game {
             }
         }
         self.handler_multiplayer();
+        self.send_sync();
+        self.update_menu();
+        self.update_slider();
         let cursor_window_pos = self.geng.window().cursor_position().unwrap_or(vec2::ZERO);
         let cursor_pos = self
             .camera
             .screen_to_world(self.framebuffer_size, cursor_window_pos.map(|x| x as f32));
-        self.baby_control(cursor_pos);
-        let target_pos = if let Some(baby) = &mut self.baby {
-            baby.pos
+        let input = self.control_input(cursor_pos, delta_time);
+        self.baby_control(input, delta_time);
+        if self.editor.is_some() && self.baby.is_none() {
+            self.update_editor_camera(cursor_pos, delta_time);
+        } else if self.baby.is_none() && self.watched_race().is_some() {
+            self.update_spectator_camera(cursor_pos, delta_time);
         } else {
-            self.others
-                .values()
-                .filter_map(|other| other.baby.as_ref())
-                .map(|baby| baby.pos)
-                .max_by_key(|pos| r32(pos.y))
-                .unwrap_or(vec2::ZERO)
-        };
-        self.camera.center += (target_pos - self.camera.center)
-            * (delta_time * self.assets.config.camera.speed).min(1.0);
+            let target_pos = if let Some(baby) = &mut self.baby {
+                baby.pos
+            } else {
+                self.others
+                    .values()
+                    .filter_map(|other| other.baby.as_ref())
+                    .map(|baby| baby.pos)
+                    .max_by_key(|pos| r32(pos.y))
+                    .unwrap_or(vec2::ZERO)
+            };
+            self.camera.center += (target_pos - self.camera.center)
+                * (delta_time * self.assets.config.camera.speed * self.settings.camera_speed)
+                    .min(1.0);
+        }
 
         self.prev_cursor_pos = cursor_pos;
     }
@@ -1089,8 +2017,9 @@ fn main() {
     if cli_args.server.is_some() && cli_args.connect.is_none() {
         #[cfg(not(target_arch = "wasm32"))]
         {
-            let server =
-                geng::net::Server::new(server::App::new(), cli_args.server.as_deref().unwrap());
+            let app = server::App::new();
+            app.spawn_console();
+            let server = geng::net::Server::new(app, cli_args.server.as_deref().unwrap());
             let server_handle = server.handle();
             ctrlc::set_handler(move || server_handle.shutdown()).unwrap();
             server.run();
@@ -1110,16 +2039,19 @@ fn main() {
 
         let mut geng_options = geng::ContextOptions::default();
         geng_options.with_cli(&cli_args.geng);
+        let dev = cli_args.dev;
         Geng::run_with(&geng_options, move |geng| async move {
-            let connection = geng::net::client::connect(&cli_args.connect.unwrap())
+            let mut connection = geng::net::client::connect(&cli_args.connect.unwrap())
                 .await
                 .unwrap();
+            // The server only waits identify_timeout for this, loading assets can take longer
+            connection.send(ClientMessage::Identify(player_id()));
             let assets = geng
                 .asset_manager()
                 .load(run_dir().join("assets"))
                 .await
-                .expect("failed to load assets");
-            geng.run_state(Game::new(&geng, &assets, connection).await)
+                .unwrap_or_else(|e| panic!("failed to load assets: {e:#}"));
+            geng.run_state(Game::new(&geng, &assets, connection, dev).await)
                 .await
         });
 
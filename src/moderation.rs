@@ -0,0 +1,67 @@
+use super::*;
+
+/// Random id kept in preferences, identifies the player to the server across reconnects
+pub fn player_id() -> String {
+    if let Some(id) = preferences::load("player_id") {
+        return id;
+    }
+    let id = format!("{:016x}", thread_rng().gen::<u64>());
+    preferences::save("player_id", &id);
+    id
+}
+
+impl Game {
+    /// Muted players' chat and emotes are hidden
+    pub fn is_muted(&self, id: ClientId) -> bool {
+        self.muted.contains(&id)
+    }
+
+    pub fn toggle_mute(&mut self, id: ClientId) {
+        if !self.muted.remove(&id) {
+            self.muted.insert(id);
+        }
+    }
+
+    pub fn report(&mut self, id: ClientId) {
+        if self.reported.insert(id) {
+            self.connection.send(ClientMessage::Report(id));
+        }
+    }
+
+    /// Everyone else on the server with mute and report buttons
+    pub fn players_menu(&self) -> Vec<MenuWidget> {
+        let rows: Vec<MenuWidget> = self
+            .others
+            .iter()
+            .map(|(&id, client)| {
+                Widget::Row(vec![
+                    Widget::Label(client.name.clone()),
+                    Widget::Button(
+                        if self.is_muted(id) { "unmute" } else { "mute" }.to_owned(),
+                        MenuItemAction::ToggleMute(id),
+                    ),
+                    if self.reported.contains(&id) {
+                        Widget::Label("reported".to_owned())
+                    } else {
+                        Widget::Button("report".to_owned(), MenuItemAction::Report(id))
+                    },
+                ])
+            })
+            .collect();
+        vec![
+            if rows.is_empty() {
+                Widget::Label("nobody else is here".to_owned())
+            } else {
+                Widget::List {
+                    id: "players",
+                    rows: 8,
+                    items: rows,
+                }
+            },
+            Widget::Button(
+                "back".to_owned(),
+                MenuItemAction::OpenScreen(MenuScreen::Main),
+            ),
+        ]
+    }
+}
@@ -1,19 +1,166 @@
 use super::*;
 
+const CONSOLE_HELP: &str = "\
+clients - list connected clients
+races - list races in progress
+kick <client id> - remove a client from the game and refuse its player id for
+    kick_ban_duration seconds. The net server can not close connections,
+    so the socket stays open and is ignored until the player leaves
+end <race id> - end a race for everyone in it
+announce <text> - send a chat message to everyone
+reload - reload server.toml and the ban list
+help - show this list";
+
 #[derive(Deserialize)]
 struct Config {
     race_timer: f64,
     spawn_gap: f32,
     start_y: f32,
+    /// How much of the penetration is resolved per sync, split between both babies
+    bump_strength: f32,
+    default_track: String,
+    max_track_length: f32,
+    max_track_width: f32,
+    max_obstacles: usize,
+    max_decorations: usize,
+    max_checkpoints: usize,
+    max_path_points: usize,
+    max_path_smoothing: usize,
+    /// Seconds between state syncs for spectators
+    spectator_sync_interval: f32,
+    max_chat_length: usize,
+    /// Most chat messages kept for a client until it syncs
+    chat_queue_length: usize,
+    /// Minimum seconds between chat messages from one client
+    chat_interval: f64,
+    /// Seconds an emote stays visible
+    emote_duration: f64,
+    /// File with banned player ids, relative to the run dir
+    #[serde(default)]
+    ban_list: Option<String>,
+    /// File reports are appended to, relative to the run dir, otherwise they are only logged
+    #[serde(default)]
+    reports: Option<String>,
+    /// Latest chat messages of the reported player saved with a report
+    report_chat_lines: usize,
+    /// Minimum seconds between custom track uploads from one client
+    upload_interval: f64,
+    /// Seconds a client has after connecting to send its player id
+    identify_timeout: f64,
+    /// Seconds a player kicked from the console can not rejoin for
+    kick_ban_duration: f64,
+    /// Fastest progress along the track per second accepted from a client
+    max_crawl_speed: f32,
+}
+
+impl Config {
+    fn validate(&self, tracks: &BTreeMap<String, Arc<Track>>) -> Result<(), String> {
+        let positive = [
+            ("race_timer", self.race_timer as f32),
+            ("spawn_gap", self.spawn_gap),
+            ("max_track_length", self.max_track_length),
+            ("max_track_width", self.max_track_width),
+            ("max_crawl_speed", self.max_crawl_speed),
+            ("identify_timeout", self.identify_timeout as f32),
+            ("emote_duration", self.emote_duration as f32),
+        ];
+        for (field, value) in positive {
+            if !(value.is_finite() && value > 0.0) {
+                return Err(format!("{field} must be a positive number"));
+            }
+        }
+        let non_negative = [
+            ("start_y", self.start_y),
+            ("spectator_sync_interval", self.spectator_sync_interval),
+            ("chat_interval", self.chat_interval as f32),
+            ("upload_interval", self.upload_interval as f32),
+            ("kick_ban_duration", self.kick_ban_duration as f32),
+        ];
+        for (field, value) in non_negative {
+            if !(value.is_finite() && value >= 0.0) {
+                return Err(format!("{field} must not be negative"));
+            }
+        }
+        if !(0.0..=1.0).contains(&self.bump_strength) {
+            return Err("bump_strength must be between 0 and 1".to_owned());
+        }
+        if self.start_y >= self.max_track_length {
+            return Err("start_y must be less than max_track_length".to_owned());
+        }
+        if self.max_chat_length == 0 {
+            return Err("max_chat_length must be positive".to_owned());
+        }
+        if self.chat_queue_length == 0 {
+            return Err("chat_queue_length must be positive".to_owned());
+        }
+        if !tracks.contains_key(&self.default_track) {
+            return Err(format!(
+                "default_track {:?} is not in assets/tracks",
+                self.default_track
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Parts of the client config the server needs to know about
+#[derive(Deserialize)]
+struct GameConfig {
+    baby: BabyConfig,
+    obstacles: HashMap<ObstacleKind, ObstacleConfig>,
+}
+
+impl GameConfig {
+    fn validate(&self) -> Result<(), String> {
+        self.baby.validate()?;
+        for kind in ObstacleKind::all() {
+            if !self.obstacles.contains_key(&kind) {
+                return Err(format!("obstacles.{kind:?} is missing"));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(PartialEq, Eq)]
+enum ChatRoom {
+    Global,
+    /// Players waiting for the host's race to start
+    Lobby(ClientId),
+    /// Racers and spectators of a race
+    Race(RaceId),
 }
 
 struct RaceState {
     start: Timer,
     finished: usize,
+    lanes: bool,
+    track_id: String,
+    track: Arc<Track>,
 }
 
 struct State {
-    config: Config,
+    config: Arc<Config>,
+    game_config: Arc<GameConfig>,
+    tracks: BTreeMap<String, Arc<Track>>,
+    /// Tracks uploaded by hosts, used when their race starts
+    custom_tracks: HashMap<ClientId, Arc<Track>>,
+    /// Collision pushes not yet sent to the client
+    bumps: HashMap<ClientId, vec2<f32>>,
+    /// Chat messages (sender, name, text) not yet sent to the client
+    chat: HashMap<ClientId, VecDeque<(Option<ClientId>, String, String)>>,
+    /// Messages from the admin console not yet sent to the client
+    pending: HashMap<ClientId, Vec<ServerMessage>>,
+    /// Latest chat messages of each client, saved with reports
+    recent_chat: HashMap<ClientId, VecDeque<String>>,
+    /// When the current emote of each client was sent
+    emotes: HashMap<ClientId, Timer>,
+    /// Banned player ids. The net server does not expose client addresses,
+    /// so bans go by the persistent id clients send right after connecting
+    bans: HashSet<String>,
+    player_ids: HashMap<ClientId, String>,
+    /// Player ids kicked from the console and when, refused for `kick_ban_duration`
+    kicks: HashMap<String, Timer>,
     next_race_id: RaceId,
     next_client_id: ClientId,
     races: HashMap<RaceId, RaceState>,
@@ -21,22 +168,467 @@ struct State {
 }
 
 impl State {
-    fn find_new_spawn_pos(&self) -> vec2<f32> {
-        let mut used_x = HashSet::new();
-        for client in self.clients.values() {
-            if let Some(baby) = &client.baby {
-                used_x.insert((baby.pos.x / self.config.spawn_gap).round() as i32);
+    /// Keeps the baby between the walls or in its lane, which the client should already do
+    fn constrain_to_track(&mut self, id: ClientId) {
+        let client = &self.clients[&id];
+        let (Some(baby), Some(race)) = (
+            &client.baby,
+            client.race_id.and_then(|race_id| self.races.get(&race_id)),
+        ) else {
+            return;
+        };
+        let (center, half_width) = match &client.lane {
+            Some(lane) => (lane.center, lane.width / 2.0),
+            None => (0.0, race.track.width / 2.0),
+        };
+        let max_offset = (half_width - baby.radius).max(0.0);
+        let projection = race.track.project(baby.pos, 0.0);
+        let offset = projection
+            .offset
+            .clamp(center - max_offset, center + max_offset);
+        if offset != projection.offset {
+            let pos = race.track.unproject(TrackPoint {
+                offset,
+                ..projection
+            });
+            self.clients
+                .get_mut(&id)
+                .unwrap()
+                .baby
+                .as_mut()
+                .unwrap()
+                .pos = pos;
+        }
+    }
+    /// Pushes the baby apart from the others in its race and returns its own push.
+    /// The other halves are queued in `bumps` and the pushes already queued count as applied,
+    /// so every overlap is resolved once no matter who syncs first
+    fn resolve_collisions(&mut self, id: ClientId) -> vec2<f32> {
+        let client = &self.clients[&id];
+        let (Some(baby), Some(race_id)) = (&client.baby, client.race_id) else {
+            return vec2::ZERO;
+        };
+        if self.races[&race_id].lanes {
+            return vec2::ZERO;
+        }
+        let circles = baby.collision_circles(&self.game_config.baby);
+        let mut own_push = vec2::ZERO;
+        let mut bumps = Vec::new();
+        for (&other_id, other) in &self.clients {
+            if other_id == id || other.race_id != Some(race_id) {
+                continue;
+            }
+            let Some(other_baby) = &other.baby else {
+                continue;
+            };
+            let pending = self.bumps.get(&other_id).copied().unwrap_or(vec2::ZERO);
+            let other_circles: Vec<(vec2<f32>, f32)> = other_baby
+                .collision_circles(&self.game_config.baby)
+                .into_iter()
+                .map(|(pos, radius)| (pos + pending, radius))
+                .collect();
+            let deepest = circles
+                .iter()
+                .flat_map(|a| other_circles.iter().map(move |b| (a, b)))
+                .filter_map(|(&(a_pos, a_radius), &(b_pos, b_radius))| {
+                    let delta_pos = b_pos - a_pos;
+                    let penetration = a_radius + b_radius - delta_pos.len();
+                    (delta_pos.len() > 1e-3 && penetration > 0.0)
+                        .then(|| delta_pos.normalize() * penetration)
+                })
+                .max_by_key(|penetration| r32(penetration.len()));
+            if let Some(penetration) = deepest {
+                let push = penetration * self.config.bump_strength / 2.0;
+                own_push -= push;
+                bumps.push((other_id, push));
+            }
+        }
+        for (id, push) in bumps {
+            *self.bumps.entry(id).or_insert(vec2::ZERO) += push;
+        }
+        own_push
+    }
+    fn track(&self, id: &str) -> Arc<Track> {
+        self.tracks
+            .get(id)
+            .unwrap_or(&self.tracks[&self.config.default_track])
+            .clone()
+    }
+    /// Clients in the same chat room see each other's messages
+    fn chat_room(&self, id: ClientId) -> ChatRoom {
+        let client = &self.clients[&id];
+        if let Some(race_id) = client.spectating {
+            return ChatRoom::Race(race_id);
+        }
+        if let (Some(race_id), Some(_)) = (client.race_id, &client.baby) {
+            return ChatRoom::Race(race_id);
+        }
+        if client.hosting_race {
+            return ChatRoom::Lobby(id);
+        }
+        match client.joined {
+            Some(host) => ChatRoom::Lobby(host),
+            None => ChatRoom::Global,
+        }
+    }
+    /// Drops the oldest messages of clients that stopped syncing
+    fn queue_chat(&mut self, id: ClientId, from: Option<ClientId>, name: &str, text: &str) {
+        let queue = self.chat.entry(id).or_default();
+        queue.push_back((from, name.to_owned(), text.to_owned()));
+        while queue.len() > self.config.chat_queue_length {
+            queue.pop_front();
+        }
+    }
+    /// Takes the client out of the game, ending its race if it was the last one crawling
+    /// and sending those who joined it back to the lobby
+    fn remove_client(&mut self, id: ClientId) -> Option<ClientServerState> {
+        let client = self.clients.remove(&id)?;
+        if let Some(race_id) = client.race_id {
+            self.end_race_if_over(race_id);
+        }
+        for other in self.clients.values_mut() {
+            if other.joined == Some(id) {
+                other.joined = None;
+            }
+        }
+        self.bumps.remove(&id);
+        self.emotes.remove(&id);
+        self.custom_tracks.remove(&id);
+        Some(client)
+    }
+    /// Removes the race once nobody is crawling in it anymore.
+    /// Finished racers keep their race id until then so their times stay in the standings
+    fn end_race_if_over(&mut self, race_id: RaceId) {
+        if self
+            .clients
+            .values()
+            .any(|client| client.race_id == Some(race_id) && client.baby.is_some())
+        {
+            return;
+        }
+        self.races.remove(&race_id);
+        for client in self.clients.values_mut() {
+            if client.race_id == Some(race_id) {
+                client.race_id = None;
+                client.lane = None;
             }
         }
-        let unused_x = (0..)
-            .flat_map(|abs| [-abs, abs])
-            .find(|x| !used_x.contains(x))
-            .unwrap();
-        vec2(unused_x as f32 * self.config.spawn_gap, self.config.start_y)
     }
-    fn sync_message(&self) -> ServerMessage {
-        ServerMessage::StateSync {
-            clients: self.clients.clone(),
+    /// Spectators only get the state of the race they are watching
+    fn sync_message(&self, id: ClientId) -> ServerMessage {
+        let clients = match self.clients[&id].spectating {
+            Some(race_id) => self
+                .clients
+                .iter()
+                .filter(|&(&other_id, other)| other_id == id || other.race_id == Some(race_id))
+                .map(|(&id, client)| (id, client.clone()))
+                .collect(),
+            None => self.clients.clone(),
+        };
+        ServerMessage::StateSync { clients }
+    }
+    /// Appends the report with the target's latest chat messages to the reports file
+    fn save_report(&self, reporter: ClientId, target: ClientId) {
+        let describe = |id: ClientId| {
+            format!(
+                "{} ({})",
+                self.clients[&id].name,
+                self.player_ids
+                    .get(&id)
+                    .map_or("unidentified", |id| id.as_str()),
+            )
+        };
+        let time = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs());
+        let chat: Vec<&str> = self
+            .recent_chat
+            .get(&target)
+            .into_iter()
+            .flatten()
+            .map(|text| text.as_str())
+            .collect();
+        let line = format!(
+            "{time} {} reported {}: {}\n",
+            describe(reporter),
+            describe(target),
+            chat.join(" | "),
+        );
+        log::info!("{}", line.trim_end());
+        let Some(file) = &self.config.reports else {
+            return;
+        };
+        let path = run_dir().join(file);
+        let result = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .and_then(|mut file| std::io::Write::write_all(&mut file, line.as_bytes()));
+        if let Err(e) = result {
+            log::error!("failed to save report to {path:?}: {e}");
+        }
+    }
+    /// Runs an admin console command, returning what to print
+    fn console_command(&mut self, command: &str, args: &str) -> String {
+        match command {
+            "clients" => {
+                let lines: Vec<String> = self
+                    .clients
+                    .iter()
+                    .map(|(id, client)| {
+                        let status = match client.race_id {
+                            Some(race_id) if client.baby.is_some() => {
+                                format!("racing in {race_id}")
+                            }
+                            _ => match (client.spectating, client.joined) {
+                                (Some(race_id), _) => format!("watching {race_id}"),
+                                (None, Some(host)) => format!("joined {host}"),
+                                (None, None) if client.hosting_race => "hosting".to_owned(),
+                                (None, None) => "in lobby".to_owned(),
+                            },
+                        };
+                        format!(
+                            "{id} {} ({}) {status}",
+                            client.name,
+                            self.player_ids
+                                .get(id)
+                                .map_or("unidentified", |id| id.as_str()),
+                        )
+                    })
+                    .collect();
+                if lines.is_empty() {
+                    "no clients".to_owned()
+                } else {
+                    lines.join("\n")
+                }
+            }
+            "races" => {
+                let lines: Vec<String> = self
+                    .races
+                    .iter()
+                    .filter_map(|(id, race)| {
+                        let racers: Vec<&str> = self
+                            .clients
+                            .values()
+                            .filter(|client| client.race_id == Some(*id) && client.baby.is_some())
+                            .map(|client| client.name.as_str())
+                            .collect();
+                        (!racers.is_empty()).then(|| {
+                            format!(
+                                "{id} on {}, {:.0}s, {} finished, racing: {}",
+                                race.track_id,
+                                race.start.elapsed().as_secs_f64(),
+                                race.finished,
+                                racers.join(", "),
+                            )
+                        })
+                    })
+                    .collect();
+                if lines.is_empty() {
+                    "no races in progress".to_owned()
+                } else {
+                    lines.join("\n")
+                }
+            }
+            "kick" => {
+                let Ok(id) = args.parse::<ClientId>() else {
+                    return "usage: kick <client id>".to_owned();
+                };
+                let Some(client) = self.remove_client(id) else {
+                    return format!("no client {id}");
+                };
+                // Delivered with the reply to the next message from the client
+                self.pending
+                    .entry(id)
+                    .or_default()
+                    .push(ServerMessage::Kicked);
+                if let Some(player_id) = self.player_ids.get(&id) {
+                    self.kicks.insert(player_id.clone(), Timer::new());
+                }
+                format!(
+                    "kicked {}, the connection stays open but is ignored and reconnecting is refused for {}s",
+                    client.name, self.config.kick_ban_duration,
+                )
+            }
+            "end" => {
+                let Ok(race_id) = args.parse::<RaceId>() else {
+                    return "usage: end <race id>".to_owned();
+                };
+                if self.races.remove(&race_id).is_none() {
+                    return format!("no race {race_id}");
+                }
+                for (&id, client) in &mut self.clients {
+                    if client.race_id != Some(race_id) {
+                        continue;
+                    }
+                    client.race_id = None;
+                    client.lane = None;
+                    if client.baby.take().is_some() {
+                        self.bumps.remove(&id);
+                        self.pending
+                            .entry(id)
+                            .or_default()
+                            .push(ServerMessage::RaceEnded);
+                    }
+                }
+                format!("ended race {race_id}")
+            }
+            "announce" => {
+                if args.is_empty() {
+                    return "usage: announce <text>".to_owned();
+                }
+                let ids: Vec<ClientId> = self.clients.keys().copied().collect();
+                for id in ids {
+                    self.queue_chat(id, None, "announcement", args);
+                }
+                format!("announced to {} clients", self.clients.len())
+            }
+            "help" => CONSOLE_HELP.to_owned(),
+            _ => format!("unknown command {command:?}, type help for the list"),
+        }
+    }
+}
+
+/// Checks an uploaded track against the limits, slow for big tracks so it runs without the state lock
+fn validate_track(config: &Config, game_config: &GameConfig, track: &Track) -> Result<(), String> {
+    if track.name.chars().count() > 30 {
+        return Err("track name is too long".to_owned());
+    }
+    if track.theme.chars().count() > 30 {
+        return Err("track theme is too long".to_owned());
+    }
+    if track.path.len() < 2 && !(track.length > 0.0 && track.length <= config.max_track_length) {
+        return Err(format!(
+            "track length must be between 0 and {}",
+            config.max_track_length
+        ));
+    }
+    if !(track.width > 0.0 && track.width <= config.max_track_width) {
+        return Err(format!(
+            "track width must be between 0 and {}",
+            config.max_track_width
+        ));
+    }
+    if track.path.len() > config.max_path_points || track.smoothing > config.max_path_smoothing {
+        return Err("track path is too complex".to_owned());
+    }
+    if !track
+        .path
+        .iter()
+        .all(|point| point.x.is_finite() && point.y.is_finite())
+    {
+        return Err("invalid track path".to_owned());
+    }
+    let total_length = track.total_length();
+    if total_length > config.max_track_length {
+        return Err(format!(
+            "track path is longer than {}",
+            config.max_track_length
+        ));
+    }
+    if !(track.finish > config.start_y && track.finish <= total_length) {
+        return Err("finish must be between the start and the end of the track".to_owned());
+    }
+    if track.checkpoints.len() > config.max_checkpoints
+        || !track
+            .checkpoints
+            .iter()
+            .all(|checkpoint| checkpoint.is_finite())
+    {
+        return Err("invalid checkpoints".to_owned());
+    }
+    if !track
+        .checkpoints
+        .iter()
+        .all(|&checkpoint| checkpoint > config.start_y && checkpoint < track.finish)
+        || !track.checkpoints.windows(2).all(|pair| pair[0] < pair[1])
+    {
+        return Err("checkpoints must be in order between the start and the finish".to_owned());
+    }
+    if !(track.parents_height >= 0.0 && track.parents_height <= config.max_track_length) {
+        return Err("parents height is out of range".to_owned());
+    }
+    if track.obstacles.len() > config.max_obstacles {
+        return Err(format!("too many obstacles (max {})", config.max_obstacles));
+    }
+    if track.decorations.len() > config.max_decorations {
+        return Err(format!(
+            "too many decorations (max {})",
+            config.max_decorations
+        ));
+    }
+    let valid_box = |pos: vec2<f32>, size: vec2<f32>| {
+        pos.x.is_finite()
+            && pos.y.is_finite()
+            && size.x > 0.0
+            && size.y > 0.0
+            && size.x <= config.max_track_width
+            && size.y <= config.max_track_length
+    };
+    if !track
+        .obstacles
+        .iter()
+        .all(|obstacle| valid_box(obstacle.pos, obstacle.size) && obstacle.angle.is_finite())
+    {
+        return Err("invalid obstacle".to_owned());
+    }
+    if !track
+        .decorations
+        .iter()
+        .all(|decoration| valid_box(decoration.pos, decoration.size))
+    {
+        return Err("invalid decoration".to_owned());
+    }
+    if !track.is_finish_reachable(config.start_y, game_config.baby.radius, |kind| {
+        game_config.obstacles[&kind].solid
+    }) {
+        return Err("finish can not be reached".to_owned());
+    }
+    Ok(())
+}
+
+/// How often server.toml and the ban list are checked for changes
+const CONFIG_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
+fn config_path() -> std::path::PathBuf {
+    run_dir().join("assets").join("server.toml")
+}
+
+fn load_config() -> anyhow::Result<Config> {
+    futures::executor::block_on(file::load_detect(config_path()))
+        .map_err(|e| e.context("failed to load server.toml"))
+}
+
+/// Loads server.toml and the ban list again, keeping the current ones if the config is invalid.
+/// Files are read without holding the state lock, then both are swapped in together.
+/// Running races keep their spawn positions, new settings apply to the next ones
+fn reload_config(state: &Mutex<State>) -> Result<(), String> {
+    let tracks = state.lock().unwrap().tracks.clone();
+    let config = load_config().map_err(|e| format!("{e:#}"))?;
+    config.validate(&tracks)?;
+    let bans = load_bans(config.ban_list.as_deref());
+    let mut state = state.lock().unwrap();
+    state.config = Arc::new(config);
+    state.bans = bans;
+    Ok(())
+}
+
+/// Reads player ids from the ban list, one per line, `#` starts a comment
+fn load_bans(file: Option<&str>) -> HashSet<String> {
+    let Some(file) = file else {
+        return HashSet::new();
+    };
+    let path = run_dir().join(file);
+    match std::fs::read_to_string(&path) {
+        Ok(contents) => contents
+            .lines()
+            .map(|line| line.split('#').next().unwrap().trim())
+            .filter(|id| !id.is_empty())
+            .map(|id| id.to_owned())
+            .collect(),
+        Err(e) => {
+            log::warn!("failed to read ban list {path:?}: {e}");
+            HashSet::new()
         }
     }
 }
@@ -47,18 +639,106 @@ pub struct App {
 
 impl App {
     pub fn new() -> Self {
-        Self {
+        let tracks: BTreeMap<String, Arc<Track>> =
+            load_tracks(&run_dir().join("assets").join("tracks"))
+                .unwrap_or_else(|e| panic!("{e:#}"))
+                .into_iter()
+                .map(|(id, track)| (id, Arc::new(track)))
+                .collect();
+        let config = load_config().unwrap_or_else(|e| panic!("{e:#}"));
+        if let Err(e) = config.validate(&tracks) {
+            panic!("invalid server.toml: {e}");
+        }
+        let game_config: GameConfig = futures::executor::block_on(file::load_detect(
+            run_dir().join("assets").join("config.toml"),
+        ))
+        .unwrap_or_else(|e| panic!("failed to load config.toml: {e:#}"));
+        if let Err(e) = game_config.validate() {
+            panic!("invalid config.toml: {e}");
+        }
+        let bans = load_bans(config.ban_list.as_deref());
+        let app = Self {
             state: Arc::new(Mutex::new(State {
+                tracks,
+                custom_tracks: default(),
                 next_race_id: 0,
                 races: default(),
-                config: futures::executor::block_on(file::load_detect(
-                    run_dir().join("assets").join("server.toml"),
-                ))
-                .unwrap(),
+                config: Arc::new(config),
+                game_config: Arc::new(game_config),
+                bumps: default(),
+                chat: default(),
+                pending: default(),
+                recent_chat: default(),
+                emotes: default(),
+                bans,
+                player_ids: default(),
+                kicks: default(),
                 next_client_id: 0,
                 clients: default(),
             })),
-        }
+        };
+        app.spawn_config_watcher();
+        app
+    }
+
+    /// Reloads server.toml and the ban list whenever either is modified, logging invalid changes
+    fn spawn_config_watcher(&self) {
+        let state = self.state.clone();
+        std::thread::spawn(move || {
+            let modified_at = |path: &std::path::Path| {
+                std::fs::metadata(path)
+                    .and_then(|metadata| metadata.modified())
+                    .ok()
+            };
+            // Editing either server.toml or the ban list it points to triggers a reload
+            let modified = || {
+                let ban_list = state.lock().unwrap().config.ban_list.clone();
+                (
+                    modified_at(&config_path()),
+                    ban_list.and_then(|file| modified_at(&run_dir().join(file))),
+                )
+            };
+            let mut last_modified = modified();
+            loop {
+                std::thread::sleep(CONFIG_POLL_INTERVAL);
+                let current = modified();
+                if current == last_modified {
+                    continue;
+                }
+                last_modified = current;
+                match reload_config(&state) {
+                    Ok(()) => log::info!("reloaded server.toml and the ban list"),
+                    Err(e) => log::error!("rejected server.toml change: {e}"),
+                }
+            }
+        });
+    }
+
+    /// Reads admin commands from stdin on a separate thread
+    pub fn spawn_console(&self) {
+        let state = self.state.clone();
+        std::thread::spawn(move || {
+            for line in std::io::stdin().lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                let line = line.trim();
+                if line.is_empty() {
+                    continue;
+                }
+                let (command, args) = line.split_once(' ').unwrap_or((line, ""));
+                // Reloading reads files, so it runs outside the state lock
+                let output = if command == "reload" {
+                    match reload_config(&state) {
+                        Ok(()) => "reloaded server.toml and the ban list".to_owned(),
+                        Err(e) => format!("rejected server.toml: {e}"),
+                    }
+                } else {
+                    state.lock().unwrap().console_command(command, args.trim())
+                };
+                println!("{output}");
+            }
+        });
     }
 }
 
@@ -66,17 +746,93 @@ pub struct Client {
     id: ClientId,
     state: Arc<Mutex<State>>,
     sender: Box<dyn geng::net::Sender<ServerMessage>>,
+    /// Clients that take longer than `identify_timeout` to identify are refused
+    connected: Timer,
+    /// Time since the state was last sent, limits the rate for spectators
+    last_sync: Timer,
+    /// Time since the last chat message, `None` before the first one
+    last_chat: Option<Timer>,
+    /// Players already reported by this client
+    reported: HashSet<ClientId>,
+    /// Time since the last track upload, `None` before the first one
+    last_upload: Option<Timer>,
+    /// Time since the progress was last updated, bounds how far it can move
+    last_progress: Timer,
 }
 
 impl Drop for Client {
     fn drop(&mut self) {
         let mut state = self.state.lock().unwrap();
-        let _client = state.clients.remove(&self.id).unwrap();
+        state.remove_client(self.id);
+        state.chat.remove(&self.id);
+        state.pending.remove(&self.id);
+        state.recent_chat.remove(&self.id);
+        state.player_ids.remove(&self.id);
     }
 }
 
 impl geng::net::Receiver<ClientMessage> for Client {
     fn handle(&mut self, message: ClientMessage) {
+        {
+            let mut state = self.state.lock().unwrap();
+            // Clients only join the game once their player id is checked against the ban list
+            if !state.player_ids.contains_key(&self.id) {
+                let ClientMessage::Identify(player_id) = message else {
+                    return;
+                };
+                let player_id: String = player_id
+                    .chars()
+                    .filter(|c| c.is_ascii_alphanumeric())
+                    .take(32)
+                    .collect();
+                let kick_ban_duration = state.config.kick_ban_duration;
+                state
+                    .kicks
+                    .retain(|_, timer| timer.elapsed().as_secs_f64() < kick_ban_duration);
+                let refusal = if state.bans.contains(&player_id) {
+                    log::info!("refused banned player {player_id}");
+                    Some(ServerMessage::Banned)
+                } else if state.kicks.contains_key(&player_id) {
+                    log::info!("refused recently kicked player {player_id}");
+                    Some(ServerMessage::Kicked)
+                } else if self.connected.elapsed().as_secs_f64() > state.config.identify_timeout {
+                    Some(ServerMessage::Kicked)
+                } else {
+                    None
+                };
+                // Refused clients keep their id so they can not identify again
+                state.player_ids.insert(self.id, player_id);
+                match refusal {
+                    Some(message) => self.sender.send(message),
+                    None => {
+                        let client = ClientServerState {
+                            name: "baby".to_owned(),
+                            baby: None,
+                            hosting_race: false,
+                            lanes: false,
+                            lane: None,
+                            track: state.config.default_track.clone(),
+                            progress: 0.0,
+                            finish_time: None,
+                            joined: None,
+                            race_id: None,
+                            spectating: None,
+                            emote: None,
+                        };
+                        state.clients.insert(self.id, client);
+                        self.sender.send(state.sync_message(self.id));
+                    }
+                }
+                return;
+            }
+            // Banned and kicked clients are not in the state and are ignored from then on
+            if !state.clients.contains_key(&self.id) {
+                for message in state.pending.remove(&self.id).unwrap_or_default() {
+                    self.sender.send(message);
+                }
+                return;
+            }
+        }
         match message {
             ClientMessage::Name(name) => {
                 let name: String = name
@@ -90,17 +846,134 @@ impl geng::net::Receiver<ClientMessage> for Client {
                 self.sender.send(ServerMessage::Name(name.clone()));
                 client.name = name;
             }
+            ClientMessage::UploadTrack(mut track) => {
+                let (config, game_config) = {
+                    let state = self.state.lock().unwrap();
+                    (state.config.clone(), state.game_config.clone())
+                };
+                if let Some(timer) = &self.last_upload {
+                    if timer.elapsed().as_secs_f64() < config.upload_interval {
+                        self.sender.send(ServerMessage::TrackRejected(
+                            "you are uploading tracks too fast".to_owned(),
+                        ));
+                        return;
+                    }
+                }
+                self.last_upload = Some(Timer::new());
+                track.name = rustrict::CensorStr::censor(track.name.as_str());
+                let result = validate_track(&config, &game_config, &track);
+                let mut state = self.state.lock().unwrap();
+                match result {
+                    Ok(()) => {
+                        state.custom_tracks.insert(self.id, Arc::new(track));
+                    }
+                    Err(reason) => {
+                        state.custom_tracks.remove(&self.id);
+                        self.sender.send(ServerMessage::TrackRejected(reason));
+                    }
+                }
+            }
+            ClientMessage::Chat(text) => {
+                let mut state = self.state.lock().unwrap();
+                if let Some(timer) = &self.last_chat {
+                    if timer.elapsed().as_secs_f64() < state.config.chat_interval {
+                        self.sender.send(ServerMessage::Chat {
+                            from: None,
+                            name: "server".to_owned(),
+                            text: "you are sending messages too fast".to_owned(),
+                        });
+                        return;
+                    }
+                }
+                let text: String = text
+                    .trim()
+                    .chars()
+                    .filter(|c| !c.is_control())
+                    .take(state.config.max_chat_length)
+                    .collect();
+                if text.is_empty() {
+                    return;
+                }
+                self.last_chat = Some(Timer::new());
+                let text = rustrict::CensorStr::censor(text.as_str());
+                let report_chat_lines = state.config.report_chat_lines;
+                let recent_chat = state.recent_chat.entry(self.id).or_default();
+                recent_chat.push_back(text.clone());
+                while recent_chat.len() > report_chat_lines {
+                    recent_chat.pop_front();
+                }
+                let name = state.clients[&self.id].name.clone();
+                let room = state.chat_room(self.id);
+                let recipients: Vec<ClientId> = state
+                    .clients
+                    .keys()
+                    .copied()
+                    .filter(|&id| state.chat_room(id) == room)
+                    .collect();
+                for id in recipients {
+                    state.queue_chat(id, Some(self.id), &name, &text);
+                }
+            }
+            ClientMessage::Identify(_) => {}
+            ClientMessage::Report(target) => {
+                let state = self.state.lock().unwrap();
+                if target == self.id
+                    || !state.clients.contains_key(&target)
+                    || !self.reported.insert(target)
+                {
+                    return;
+                }
+                state.save_report(self.id, target);
+            }
+            ClientMessage::Emote(emote) => {
+                let mut state = self.state.lock().unwrap();
+                state.clients.get_mut(&self.id).unwrap().emote = Some(emote);
+                state.emotes.insert(self.id, Timer::new());
+            }
+            ClientMessage::Spectate(race_id) => {
+                let mut state = self.state.lock().unwrap();
+                if state.clients[&self.id].baby.is_some() {
+                    // Racers can not watch other races
+                    self.sender.send(ServerMessage::Spectate {
+                        race: None,
+                        sync_interval: 0.0,
+                    });
+                    return;
+                }
+                let race = race_id.and_then(|id| Some((id, state.races.get(&id)?.track.clone())));
+                if let Some((_, track)) = &race {
+                    self.sender.send(ServerMessage::Track((**track).clone()));
+                }
+                let race = race.map(|(id, _)| id);
+                // Picking a race to watch needs the whole state at the normal rate
+                let sync_interval = match race {
+                    Some(_) => state.config.spectator_sync_interval,
+                    None => 0.0,
+                };
+                state.clients.get_mut(&self.id).unwrap().spectating = race;
+                self.sender.send(ServerMessage::Spectate {
+                    race,
+                    sync_interval,
+                });
+            }
             ClientMessage::Finish => {
                 let mut state = self.state.lock().unwrap();
                 let client = state.clients.get_mut(&self.id).unwrap();
-                client.baby = None;
-                if let Some(race_id) = client.race_id {
+                if client.baby.take().is_none() {
+                    return;
+                }
+                client.lane = None;
+                state.bumps.remove(&self.id);
+                if let Some(race_id) = state.clients[&self.id].race_id {
                     let race = state.races.get_mut(&race_id).unwrap();
                     race.finished += 1;
+                    let time = race.start.elapsed().as_secs_f64() as f32;
                     self.sender.send(ServerMessage::RaceResult {
                         rank: race.finished,
-                        time: race.start.elapsed().as_secs_f64() as f32,
+                        time,
                     });
+                    state.clients.get_mut(&self.id).unwrap().finish_time = Some(time);
+                    state.end_race_if_over(race_id);
                 }
             }
             ClientMessage::StartRace => {
@@ -119,6 +992,26 @@ impl geng::net::Receiver<ClientMessage> for Client {
                         }
                     })
                     .collect();
+                let host = &state.clients[&self.id];
+                let lanes = host.lanes;
+                let track_id = host.track.clone();
+                let track = match state.custom_tracks.get(&self.id) {
+                    Some(track) if track_id == CUSTOM_TRACK => track.clone(),
+                    _ => state.track(&track_id),
+                };
+                let lane_width = track.width / participants.len() as f32;
+                let baby_size = 2.0 * state.game_config.baby.radius;
+                if lanes && lane_width < baby_size {
+                    self.sender.send(ServerMessage::Chat {
+                        from: None,
+                        name: "server".to_owned(),
+                        text: format!(
+                            "this track only has room for {} lanes",
+                            (track.width / baby_size).floor()
+                        ),
+                    });
+                    return;
+                }
                 let race_id = state.next_race_id;
                 state.next_race_id += 1;
                 state.races.insert(
@@ -126,23 +1019,51 @@ impl geng::net::Receiver<ClientMessage> for Client {
                     RaceState {
                         start: Timer::new(),
                         finished: 0,
+                        lanes,
+                        track_id: track_id.clone(),
+                        track: track.clone(),
                     },
                 );
-                for id in participants {
-                    let baby = Baby::new(None, state.find_new_spawn_pos());
+                // Lanes split the whole track between the racers, otherwise everyone starts
+                // in a row packed closer than spawn_gap on narrow tracks
+                let gap = if lanes {
+                    lane_width
+                } else {
+                    state.config.spawn_gap.min(lane_width)
+                };
+                let first_center = -(participants.len() - 1) as f32 * gap / 2.0;
+                for (index, id) in participants.into_iter().enumerate() {
+                    let center = first_center + index as f32 * gap;
+                    let lane = lanes.then_some(Lane { center, width: gap });
+                    let pos = track.unproject(TrackPoint {
+                        progress: state.config.start_y,
+                        offset: center,
+                    });
+                    let baby = Baby::new(None, pos);
                     let client = state.clients.get_mut(&id).unwrap();
                     client.hosting_race = false;
                     client.joined = None;
                     client.race_id = Some(race_id);
+                    client.spectating = None;
+                    // Shown in the race browser while the race is on
+                    client.track = track_id.clone();
+                    client.lane = lane;
+                    client.progress = 0.0;
+                    client.finish_time = None;
                     client.baby = Some(baby);
                 }
             }
             ClientMessage::Despawn => {
                 let mut state = self.state.lock().unwrap();
+                state.bumps.remove(&self.id);
                 let client = state.clients.get_mut(&self.id).unwrap();
                 client.baby = None;
                 client.joined = None;
                 client.hosting_race = false;
+                client.lane = None;
+                if let Some(race_id) = client.race_id.take() {
+                    state.end_race_if_over(race_id);
+                }
             }
             ClientMessage::StateSync(mut update) => {
                 let mut state = self.state.lock().unwrap();
@@ -152,18 +1073,72 @@ impl geng::net::Receiver<ClientMessage> for Client {
                     }
                     update.host_race = false;
                 }
+                let track = update
+                    .track
+                    .filter(|id| {
+                        state.tracks.contains_key(id)
+                            || (id == CUSTOM_TRACK && state.custom_tracks.contains_key(&self.id))
+                    })
+                    .unwrap_or_else(|| state.config.default_track.clone());
+                let max_crawl_speed = state.config.max_crawl_speed;
                 let client = state.clients.get_mut(&self.id).unwrap();
                 if let Some(baby) = &mut client.baby {
-                    if let Some(update) = update.baby {
-                        *baby = update;
+                    if let Some(baby_update) = update.baby {
+                        *baby = baby_update;
+                        // Progress only grows as fast as anyone can crawl
+                        let max_progress = client.progress
+                            + max_crawl_speed * self.last_progress.elapsed().as_secs_f64() as f32;
+                        client.progress = update.progress.min(max_progress);
+                        self.last_progress.reset();
+                        state.constrain_to_track(self.id);
+                        // Pushes from collisions resolved when others synced come first
+                        let pending = state.bumps.remove(&self.id).unwrap_or(vec2::ZERO);
+                        let client = state.clients.get_mut(&self.id).unwrap();
+                        client.baby.as_mut().unwrap().pos += pending;
+                        let push = state.resolve_collisions(self.id);
+                        let client = state.clients.get_mut(&self.id).unwrap();
+                        client.baby.as_mut().unwrap().pos += push;
+                        let bump = pending + push;
+                        if bump != vec2::ZERO {
+                            self.sender.send(ServerMessage::Bump(bump));
+                        }
                     } else {
-                        self.sender.send(ServerMessage::Spawn(baby.pos));
+                        let pos = baby.pos;
+                        if let Some(race_id) = client.race_id {
+                            self.sender
+                                .send(ServerMessage::Track((*state.races[&race_id].track).clone()));
+                        }
+                        self.sender.send(ServerMessage::Spawn(pos));
                     }
                 } else {
+                    self.last_progress.reset();
                     client.joined = update.join_race;
                     client.hosting_race = update.host_race;
+                    client.lanes = update.lanes;
+                    client.track = track;
+                }
+                if let Some(timer) = state.emotes.get(&self.id) {
+                    if timer.elapsed().as_secs_f64() > state.config.emote_duration {
+                        state.emotes.remove(&self.id);
+                        state.clients.get_mut(&self.id).unwrap().emote = None;
+                    }
                 }
-                self.sender.send(state.sync_message());
+                for message in state.pending.remove(&self.id).unwrap_or_default() {
+                    self.sender.send(message);
+                }
+                for (from, name, text) in state.chat.remove(&self.id).unwrap_or_default() {
+                    self.sender.send(ServerMessage::Chat { from, name, text });
+                }
+                // Spectators sync on a timer, early ones are not answered.
+                // Some slack is left for network jitter
+                if state.clients[&self.id].spectating.is_some()
+                    && self.last_sync.elapsed().as_secs_f64()
+                        < state.config.spectator_sync_interval as f64 * 0.8
+                {
+                    return;
+                }
+                self.last_sync.reset();
+                self.sender.send(state.sync_message(self.id));
             }
         }
     }
@@ -180,22 +1155,28 @@ impl geng::net::server::App for App {
         let mut state = self.state.lock().unwrap();
         let id = state.next_client_id;
         state.next_client_id += 1;
-        state.clients.insert(
+        // The connection carries no address or player id, so the ban list is checked
+        // once the client identifies itself, nothing else is accepted until then
+        sender.send(ServerMessage::Auth {
             id,
-            ClientServerState {
-                name: "baby".to_owned(),
-                baby: None,
-                hosting_race: false,
-                joined: None,
-                race_id: None,
-            },
-        );
-        sender.send(ServerMessage::Auth { id });
-        sender.send(state.sync_message());
+            max_chat_length: state.config.max_chat_length,
+        });
+        sender.send(ServerMessage::TrackList(
+            state.tracks.keys().cloned().collect(),
+        ));
+        sender.send(ServerMessage::Track(
+            (*state.track(&state.config.default_track)).clone(),
+        ));
         Client {
             id,
+            connected: Timer::new(),
+            last_sync: Timer::new(),
             state: self.state.clone(),
             sender,
+            last_chat: None,
+            reported: HashSet::new(),
+            last_upload: None,
+            last_progress: Timer::new(),
         }
     }
 }
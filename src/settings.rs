@@ -0,0 +1,170 @@
+use super::*;
+
+/// Player settings stored in preferences
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub music_volume: f32,
+    pub sfx_volume: f32,
+    /// Multiplier for the configured mouse sensitivity
+    pub sensitivity: f32,
+    /// Multiplier for the configured camera follow speed
+    pub camera_speed: f32,
+    pub fullscreen: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            music_volume: 0.5,
+            sfx_volume: 1.0,
+            sensitivity: 1.0,
+            camera_speed: 1.0,
+            fullscreen: false,
+        }
+    }
+}
+
+impl Settings {
+    pub fn load() -> Self {
+        preferences::load("settings").unwrap_or_default()
+    }
+
+    pub fn save(&self) {
+        preferences::save("settings", self);
+    }
+
+    fn value_mut(&mut self, slider: Slider) -> &mut f32 {
+        match slider {
+            Slider::MusicVolume => &mut self.music_volume,
+            Slider::SfxVolume => &mut self.sfx_volume,
+            Slider::Sensitivity => &mut self.sensitivity,
+            Slider::CameraSpeed => &mut self.camera_speed,
+        }
+    }
+
+    pub fn value(&self, slider: Slider) -> f32 {
+        match slider {
+            Slider::MusicVolume => self.music_volume,
+            Slider::SfxVolume => self.sfx_volume,
+            Slider::Sensitivity => self.sensitivity,
+            Slider::CameraSpeed => self.camera_speed,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Slider {
+    MusicVolume,
+    SfxVolume,
+    Sensitivity,
+    CameraSpeed,
+}
+
+impl Slider {
+    pub fn all() -> impl Iterator<Item = Self> {
+        [
+            Self::MusicVolume,
+            Self::SfxVolume,
+            Self::Sensitivity,
+            Self::CameraSpeed,
+        ]
+        .into_iter()
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::MusicVolume => "music volume",
+            Self::SfxVolume => "sound volume",
+            Self::Sensitivity => "mouse sensitivity",
+            Self::CameraSpeed => "camera follow speed",
+        }
+    }
+
+    pub fn range(&self) -> std::ops::RangeInclusive<f32> {
+        match self {
+            Self::MusicVolume | Self::SfxVolume => 0.0..=1.0,
+            Self::Sensitivity | Self::CameraSpeed => 0.25..=4.0,
+        }
+    }
+
+    /// Position of the value along the slider, from 0 to 1
+    pub fn fraction(&self, value: f32) -> f32 {
+        let range = self.range();
+        (value - range.start()) / (range.end() - range.start())
+    }
+}
+
+impl Game {
+    pub fn play_sfx(&self, sound: &geng::Sound) {
+        let mut effect = sound.effect(self.geng.audio().default_type());
+        effect.set_volume(self.settings.sfx_volume);
+        effect.play();
+    }
+
+    pub fn settings_menu(&self) -> Vec<MenuWidget> {
+        let mut items: Vec<MenuWidget> = Slider::all()
+            .map(|slider| {
+                let value = self.settings.value(slider);
+                Widget::Slider {
+                    text: format!("{}: {:.0}%", slider.name(), value * 100.0),
+                    value: slider.fraction(value),
+                    action: MenuItemAction::DragSlider(slider),
+                }
+            })
+            .collect();
+        items.extend([
+            Widget::Button(
+                if self.settings.fullscreen {
+                    "fullscreen: on"
+                } else {
+                    "fullscreen: off"
+                }
+                .to_owned(),
+                MenuItemAction::ToggleFullscreen,
+            ),
+            Widget::Button(
+                "back".to_owned(),
+                MenuItemAction::OpenScreen(MenuScreen::Main),
+            ),
+        ]);
+        items
+    }
+
+    pub fn toggle_fullscreen(&mut self) {
+        self.settings.fullscreen = !self.settings.fullscreen;
+        self.geng.window().set_fullscreen(self.settings.fullscreen);
+        self.settings.save();
+    }
+
+    /// Follows the cursor while a slider is held, saving once released
+    pub fn update_slider(&mut self) {
+        let Some(slider) = self.dragging_slider else {
+            return;
+        };
+        let window = self.geng.window();
+        if !window.is_button_pressed(geng::MouseButton::Left) {
+            self.dragging_slider = None;
+            self.settings.save();
+            return;
+        }
+        let cursor = self.ui_cursor(
+            window
+                .cursor_position()
+                .unwrap_or(vec2::ZERO)
+                .map(|x| x as f32),
+        );
+        let Some(t) = self.ui.slider_value(
+            MenuItemAction::DragSlider(slider),
+            cursor,
+            &self.assets.config.ui,
+        ) else {
+            return;
+        };
+        let range = slider.range();
+        *self.settings.value_mut(slider) = range.start() + (range.end() - range.start()) * t;
+        if slider == Slider::MusicVolume {
+            self.music.set_volume(self.settings.music_volume);
+        }
+    }
+}
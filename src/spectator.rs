@@ -0,0 +1,200 @@
+use super::*;
+
+#[derive(Deserialize)]
+pub struct SpectatorConfig {
+    /// Extra space around racers in overview
+    overview_margin: f32,
+    zoom_speed: f32,
+    min_fov: f32,
+    max_fov: f32,
+    text_color: Rgba<f32>,
+    text_size: f32,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SpectatorTarget {
+    Racer(ClientId),
+    /// Fit all racers of the watched race
+    Overview,
+    /// Camera panned by hand
+    Free,
+}
+
+pub struct Spectator {
+    pub race: Option<RaceId>,
+    pub target: SpectatorTarget,
+    /// World position grabbed for panning the camera
+    grab: Option<vec2<f32>>,
+}
+
+impl Spectator {
+    pub fn new() -> Self {
+        Self {
+            race: None,
+            target: SpectatorTarget::Overview,
+            grab: None,
+        }
+    }
+}
+
+impl Game {
+    /// Races that still have someone crawling, with their racers
+    pub fn active_races(&self) -> BTreeMap<RaceId, Vec<ClientId>> {
+        let mut races = BTreeMap::<RaceId, Vec<ClientId>>::new();
+        for (&id, other) in &self.others {
+            if let (Some(race_id), Some(_)) = (other.race_id, &other.baby) {
+                races.entry(race_id).or_default().push(id);
+            }
+        }
+        races
+    }
+
+    pub fn watched_race(&self) -> Option<RaceId> {
+        self.spectator.as_ref()?.race
+    }
+
+    fn watched_racers(&self) -> Vec<ClientId> {
+        let Some(race) = self.watched_race() else {
+            return Vec::new();
+        };
+        self.active_races().remove(&race).unwrap_or_default()
+    }
+
+    fn cycle_spectator_target(&mut self, step: isize) {
+        let racers = self.watched_racers();
+        let Some(spectator) = &mut self.spectator else {
+            return;
+        };
+        if racers.is_empty() {
+            return;
+        }
+        let index = match spectator.target {
+            SpectatorTarget::Racer(id) => racers
+                .iter()
+                .position(|&racer| racer == id)
+                .map_or(0, |index| {
+                    (index as isize + step).rem_euclid(racers.len() as isize) as usize
+                }),
+            _ => 0,
+        };
+        spectator.target = SpectatorTarget::Racer(racers[index]);
+        self.camera.fov = Camera2dFov::MinSide(self.assets.config.camera.fov);
+    }
+
+    /// Called when a left click did not hit the menu
+    pub fn start_spectator_pan(&mut self, cursor: vec2<f32>) {
+        if let Some(spectator) = &mut self.spectator {
+            if spectator.race.is_some() {
+                spectator.grab = Some(cursor);
+                spectator.target = SpectatorTarget::Free;
+            }
+        }
+    }
+
+    pub fn handle_spectator_event(&mut self, event: &geng::Event) {
+        let config = &self.assets.config.spectator;
+        let Some(spectator) = &mut self.spectator else {
+            return;
+        };
+        match *event {
+            geng::Event::MouseRelease {
+                button: geng::MouseButton::Left,
+            } => spectator.grab = None,
+            geng::Event::Wheel { delta } if spectator.race.is_some() => {
+                if spectator.target == SpectatorTarget::Overview {
+                    spectator.target = SpectatorTarget::Free;
+                }
+                if let Camera2dFov::MinSide(fov) = &mut self.camera.fov {
+                    *fov = (*fov * (-delta as f32 * config.zoom_speed).exp())
+                        .clamp(config.min_fov, config.max_fov);
+                }
+            }
+            geng::Event::KeyPress { key } => match key {
+                geng::Key::Tab | geng::Key::ArrowRight => self.cycle_spectator_target(1),
+                geng::Key::ArrowLeft => self.cycle_spectator_target(-1),
+                geng::Key::O => spectator.target = SpectatorTarget::Overview,
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    pub fn update_spectator_camera(&mut self, cursor: vec2<f32>, delta_time: f32) {
+        let Some(spectator) = &self.spectator else {
+            return;
+        };
+        if let Some(grab) = spectator.grab {
+            self.camera.center += grab - cursor;
+            return;
+        }
+        let config = &self.assets.config;
+        let k = (delta_time * config.camera.speed * self.settings.camera_speed).min(1.0);
+        match spectator.target {
+            SpectatorTarget::Racer(id) => {
+                if let Some(baby) = self.other_babis.get(&id) {
+                    self.camera.center += (baby.pos - self.camera.center) * k;
+                } else {
+                    // They finished or left
+                    self.spectator.as_mut().unwrap().target = SpectatorTarget::Overview;
+                }
+            }
+            SpectatorTarget::Overview => {
+                let Some(aabb) = Aabb2::points_bounding_box(
+                    self.watched_racers()
+                        .iter()
+                        .filter_map(|id| self.other_babis.get(id))
+                        .map(|baby| baby.pos),
+                ) else {
+                    return;
+                };
+                let target_fov = (aabb.width().max(aabb.height())
+                    + config.spectator.overview_margin * 2.0)
+                    .clamp(config.camera.fov, config.spectator.max_fov);
+                self.camera.center += (aabb.center() - self.camera.center) * k;
+                if let Camera2dFov::MinSide(fov) = &mut self.camera.fov {
+                    *fov += (target_fov - *fov) * k;
+                }
+            }
+            SpectatorTarget::Free => {}
+        }
+    }
+
+    pub fn draw_spectator(&self, framebuffer: &mut ugli::Framebuffer) {
+        let Some(spectator) = &self.spectator else {
+            return;
+        };
+        if spectator.race.is_none() {
+            return;
+        }
+        let config = &self.assets.config.spectator;
+        let watching = match spectator.target {
+            SpectatorTarget::Racer(id) => match self.others.get(&id) {
+                Some(other) => format!("watching {}", other.name),
+                None => "watching".to_owned(),
+            },
+            SpectatorTarget::Overview => "overview".to_owned(),
+            SpectatorTarget::Free => "free camera".to_owned(),
+        };
+        let bottom = self
+            .ui_camera
+            .view_area(self.framebuffer_size)
+            .bounding_box()
+            .min
+            .y;
+        let lines = [
+            watching,
+            "Tab/arrows cycle racers, O overview, drag to pan, wheel zoom".to_owned(),
+        ];
+        for (i, line) in lines.iter().rev().enumerate() {
+            self.geng.default_font().draw(
+                framebuffer,
+                &self.ui_camera,
+                line,
+                vec2(geng::TextAlign::CENTER, geng::TextAlign::BOTTOM),
+                mat3::translate(vec2(0.0, bottom + (0.5 + i as f32) * config.text_size))
+                    * mat3::scale_uniform(config.text_size),
+                config.text_color,
+            );
+        }
+    }
+}
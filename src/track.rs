@@ -0,0 +1,314 @@
+use super::*;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ObstacleKind {
+    /// Blocks the way
+    Pillow,
+    /// Slows crawling down
+    Rug,
+    /// Makes you slide
+    Slope,
+    /// Has to be crawled over
+    Toy,
+}
+
+impl ObstacleKind {
+    pub fn all() -> impl Iterator<Item = Self> + Clone {
+        [Self::Pillow, Self::Rug, Self::Slope, Self::Toy].into_iter()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Obstacle {
+    pub kind: ObstacleKind,
+    pub pos: vec2<f32>,
+    pub size: vec2<f32>,
+    /// Direction of the slope in degrees
+    #[serde(default)]
+    pub angle: f32,
+}
+
+impl Obstacle {
+    pub fn aabb(&self) -> Aabb2<f32> {
+        Aabb2::point(self.pos).extend_symmetric(self.size / 2.0)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum DecorationKind {
+    Blanket,
+    Block,
+    Ball,
+}
+
+impl DecorationKind {
+    pub fn all() -> impl Iterator<Item = Self> + Clone {
+        [Self::Blanket, Self::Block, Self::Ball].into_iter()
+    }
+}
+
+/// Purely visual things lying around the track
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Decoration {
+    pub kind: DecorationKind,
+    pub pos: vec2<f32>,
+    pub size: vec2<f32>,
+}
+
+impl Decoration {
+    pub fn aabb(&self) -> Aabb2<f32> {
+        Aabb2::point(self.pos).extend_symmetric(self.size / 2.0)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Track {
+    pub name: String,
+    /// Length of the straight track, only used when there is no path
+    pub length: f32,
+    pub width: f32,
+    /// Key into the themes of the client config
+    pub theme: String,
+    pub parents_height: f32,
+    /// Center line of the track starting at the origin.
+    /// When empty the track goes straight up
+    #[serde(default)]
+    pub path: Vec<vec2<f32>>,
+    /// How many times to round the corners of the path
+    #[serde(default)]
+    pub smoothing: usize,
+    /// Progress along the path at which checkpoints are, in order
+    #[serde(default)]
+    pub checkpoints: Vec<f32>,
+    /// Progress along the path of the final checkpoint
+    pub finish: f32,
+    pub obstacles: Vec<Obstacle>,
+    pub decorations: Vec<Decoration>,
+    /// Segments of the center line, computed on first use.
+    /// Cleared with [Track::path_changed] after editing the length or the path
+    #[serde(skip)]
+    pub segment_cache: std::sync::OnceLock<Vec<TrackSegment>>,
+}
+
+/// Position in track space
+#[derive(Debug, Copy, Clone)]
+pub struct TrackPoint {
+    /// Distance along the center line
+    pub progress: f32,
+    /// Distance to the right of the center line
+    pub offset: f32,
+}
+
+/// Piece of the center line
+#[derive(Debug, Copy, Clone)]
+pub struct TrackSegment {
+    pub start: vec2<f32>,
+    pub end: vec2<f32>,
+    /// Progress at the start of the segment
+    pub progress: f32,
+}
+
+impl TrackSegment {
+    pub fn len(&self) -> f32 {
+        (self.end - self.start).len()
+    }
+    pub fn direction(&self) -> vec2<f32> {
+        (self.end - self.start).normalize_or_zero()
+    }
+    /// Points to the right of the direction
+    pub fn normal(&self) -> vec2<f32> {
+        let direction = self.direction();
+        vec2(direction.y, -direction.x)
+    }
+}
+
+impl Track {
+    pub fn center_line(&self) -> Vec<vec2<f32>> {
+        if self.path.len() < 2 {
+            return vec![vec2::ZERO, vec2(0.0, self.length)];
+        }
+        let mut points = self.path.clone();
+        for _ in 0..self.smoothing {
+            // Chaikin's corner cutting
+            let mut smoothed = vec![points[0]];
+            for pair in points.windows(2) {
+                let (a, b) = (pair[0], pair[1]);
+                smoothed.push(a * 0.75 + b * 0.25);
+                smoothed.push(a * 0.25 + b * 0.75);
+            }
+            smoothed.push(*points.last().unwrap());
+            points = smoothed;
+        }
+        points
+    }
+
+    pub fn segments(&self) -> &[TrackSegment] {
+        self.segment_cache.get_or_init(|| self.compute_segments())
+    }
+
+    /// Has to be called after changing anything the center line depends on
+    pub fn path_changed(&mut self) {
+        self.segment_cache = std::sync::OnceLock::new();
+    }
+
+    fn compute_segments(&self) -> Vec<TrackSegment> {
+        let mut progress = 0.0;
+        let mut segments = Vec::new();
+        for pair in self.center_line().windows(2) {
+            let segment = TrackSegment {
+                start: pair[0],
+                end: pair[1],
+                progress,
+            };
+            if segment.len() < 1e-5 {
+                continue;
+            }
+            progress += segment.len();
+            segments.push(segment);
+        }
+        segments
+    }
+
+    pub fn total_length(&self) -> f32 {
+        self.segments()
+            .last()
+            .map_or(0.0, |segment| segment.progress + segment.len())
+    }
+
+    /// Checkpoint progresses in order, ending with the finish
+    pub fn all_checkpoints(&self) -> Vec<f32> {
+        let mut checkpoints: Vec<f32> = self
+            .checkpoints
+            .iter()
+            .copied()
+            .filter(|&checkpoint| checkpoint < self.finish)
+            .collect();
+        checkpoints.sort_by(f32::total_cmp);
+        checkpoints.push(self.finish);
+        checkpoints
+    }
+
+    /// Closest point on the part of the center line after `min_progress`
+    pub fn project(&self, pos: vec2<f32>, min_progress: f32) -> TrackPoint {
+        let mut closest: Option<(f32, TrackPoint)> = None;
+        for segment in self.segments() {
+            let len = segment.len();
+            if segment.progress + len < min_progress {
+                continue;
+            }
+            let t = vec2::dot(pos - segment.start, segment.direction())
+                .clamp((min_progress - segment.progress).max(0.0), len);
+            let distance = (pos - (segment.start + segment.direction() * t)).len();
+            if closest.map_or(true, |(closest, _)| distance < closest) {
+                closest = Some((
+                    distance,
+                    TrackPoint {
+                        progress: segment.progress + t,
+                        offset: vec2::dot(pos - segment.start, segment.normal()),
+                    },
+                ));
+            }
+        }
+        closest.map_or(
+            TrackPoint {
+                progress: 0.0,
+                offset: 0.0,
+            },
+            |(_, point)| point,
+        )
+    }
+
+    /// World position of a point in track space
+    pub fn unproject(&self, point: TrackPoint) -> vec2<f32> {
+        unproject(self.segments(), point)
+    }
+
+    /// Whether a baby of given radius can get from the start line to the finish line
+    /// without crawling through solid obstacles
+    pub fn is_finish_reachable(
+        &self,
+        start: f32,
+        radius: f32,
+        is_solid: impl Fn(ObstacleKind) -> bool,
+    ) -> bool {
+        const CELL: f32 = 0.25;
+        /// Big tracks are checked on a coarser grid to keep the search cheap
+        const MAX_CELLS: f32 = 50_000.0;
+        let cell = CELL.max((self.width * self.finish / MAX_CELLS).sqrt());
+        let columns = (self.width / cell).ceil() as i32;
+        let rows = (self.finish / cell).ceil() as i32 + 1;
+        let cell_point = |x: i32, y: i32| TrackPoint {
+            progress: y as f32 * cell,
+            offset: -self.width / 2.0 + (x as f32 + 0.5) * cell,
+        };
+        let segments = self.segments();
+        let solid: Vec<Aabb2<f32>> = self
+            .obstacles
+            .iter()
+            .filter(|obstacle| is_solid(obstacle.kind))
+            .map(|obstacle| obstacle.aabb().extend_uniform(radius))
+            .collect();
+        let blocked = |point: TrackPoint| {
+            let pos = unproject(segments, point);
+            point.offset.abs() > self.width / 2.0 - radius
+                || solid.iter().any(|aabb| aabb.contains(pos))
+        };
+        let start_row = (start / cell).round() as i32;
+        let mut visited = HashSet::new();
+        let mut queue = VecDeque::new();
+        for x in 0..columns {
+            if !blocked(cell_point(x, start_row)) {
+                visited.insert((x, start_row));
+                queue.push_back((x, start_row));
+            }
+        }
+        while let Some((x, y)) = queue.pop_front() {
+            if cell_point(x, y).progress >= self.finish {
+                return true;
+            }
+            for (dx, dy) in [(1, 0), (-1, 0), (0, 1), (0, -1)] {
+                let next = (x + dx, y + dy);
+                if next.0 < 0 || next.0 >= columns || next.1 < 0 || next.1 > rows {
+                    continue;
+                }
+                if visited.insert(next) && !blocked(cell_point(next.0, next.1)) {
+                    queue.push_back(next);
+                }
+            }
+        }
+        false
+    }
+}
+
+/// World position of a point in track space on the given center line
+fn unproject(segments: &[TrackSegment], point: TrackPoint) -> vec2<f32> {
+    // Segments are sorted by progress
+    let index =
+        segments.partition_point(|segment| segment.progress + segment.len() < point.progress);
+    let segment = segments.get(index).or(segments.last());
+    match segment {
+        Some(segment) => {
+            segment.start
+                + segment.direction() * (point.progress - segment.progress)
+                + segment.normal() * point.offset
+        }
+        None => vec2(point.offset, point.progress),
+    }
+}
+
+/// Loads all tracks in the directory, keyed by file name
+#[cfg(not(target_arch = "wasm32"))]
+pub fn load_tracks(dir: &std::path::Path) -> anyhow::Result<BTreeMap<String, Track>> {
+    let mut tracks = BTreeMap::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some("toml") {
+            continue;
+        }
+        let id = path.file_stem().unwrap().to_string_lossy().into_owned();
+        let track = futures::executor::block_on(file::load_detect(&path))
+            .map_err(|e| e.context(format!("failed to load track {path:?}")))?;
+        tracks.insert(id, track);
+    }
+    Ok(tracks)
+}
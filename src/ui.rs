@@ -0,0 +1,380 @@
+use super::*;
+
+const ROW_HEIGHT: f32 = 1.0;
+
+/// Building block of menu screens, rebuilt from game state once per update
+pub enum Widget<A> {
+    Label(String),
+    Button(String, A),
+    /// Clicking performs the action, a caret is shown while the input with `id` has focus
+    TextInput {
+        id: &'static str,
+        text: String,
+        action: A,
+    },
+    /// Pressing performs the action, `value` is the fill from 0 to 1
+    Slider {
+        text: String,
+        value: f32,
+        action: A,
+    },
+    /// Widgets side by side sharing the width equally
+    Row(Vec<Widget<A>>),
+    /// Shows `rows` items at a time, scrolled with the mouse wheel
+    List {
+        id: &'static str,
+        rows: usize,
+        items: Vec<Widget<A>>,
+    },
+}
+
+impl<A> Widget<A> {
+    fn height(&self) -> f32 {
+        match self {
+            Self::Row(widgets) => widgets
+                .iter()
+                .map(|widget| widget.height())
+                .max_by_key(|&height| r32(height))
+                .unwrap_or(ROW_HEIGHT),
+            Self::List { rows, .. } => *rows as f32 * ROW_HEIGHT,
+            _ => ROW_HEIGHT,
+        }
+    }
+}
+
+/// Menu state kept between frames. The layout built by the last update is used
+/// for drawing and input until the next one
+pub struct Ui<A> {
+    layout: Layout<A>,
+    /// First visible item of each list
+    scroll: HashMap<&'static str, usize>,
+    /// Text input that has the keyboard
+    focus: Option<&'static str>,
+}
+
+impl<A> Default for Ui<A> {
+    fn default() -> Self {
+        Self {
+            layout: Layout {
+                left: 0.0,
+                right: 0.0,
+                elements: Vec::new(),
+                lists: Vec::new(),
+            },
+            scroll: HashMap::new(),
+            focus: None,
+        }
+    }
+}
+
+impl<A: Copy + PartialEq> Ui<A> {
+    pub fn set_layout(&mut self, layout: Layout<A>) {
+        self.layout = layout;
+    }
+
+    pub fn focus(&self) -> Option<&'static str> {
+        self.focus
+    }
+
+    pub fn is_focused(&self, id: &'static str) -> bool {
+        self.focus == Some(id)
+    }
+
+    /// Only records the focus, starting and stopping the window text edit is up to the caller
+    pub fn set_focus(&mut self, focus: Option<&'static str>) {
+        self.focus = focus;
+    }
+
+    /// Action of the interactive widget under the position
+    pub fn hit(&self, pos: vec2<f32>) -> Option<A> {
+        self.layout.hit(pos)
+    }
+
+    /// Value from 0 to 1 the slider with the given action would have at the position
+    pub fn slider_value(&self, action: A, pos: vec2<f32>, config: &UiConfig) -> Option<f32> {
+        self.layout.slider_value(action, pos, config)
+    }
+
+    /// Scrolls the list under the position, returns whether there was one
+    pub fn scroll(&mut self, pos: vec2<f32>, delta: f64) -> bool {
+        let Some(list) = self
+            .layout
+            .lists
+            .iter_mut()
+            .find(|list| list.aabb.contains(pos))
+        else {
+            return false;
+        };
+        let max = list.total.saturating_sub(list.rows);
+        list.first = if delta > 0.0 {
+            list.first.saturating_sub(1)
+        } else {
+            (list.first + 1).min(max)
+        };
+        self.scroll.insert(list.id, list.first);
+        true
+    }
+
+    pub fn draw(
+        &self,
+        geng: &Geng,
+        framebuffer: &mut ugli::Framebuffer,
+        camera: &Camera2d,
+        config: &UiConfig,
+        cursor: vec2<f32>,
+        show_caret: bool,
+    ) {
+        self.layout
+            .draw(geng, framebuffer, camera, config, cursor, show_caret);
+    }
+}
+
+enum Element<A> {
+    Label(String),
+    Button(String, A),
+    TextInput {
+        text: String,
+        editing: bool,
+        action: A,
+    },
+    Slider {
+        text: String,
+        value: f32,
+        action: A,
+    },
+}
+
+impl<A: Copy> Element<A> {
+    fn text(&self) -> &str {
+        match self {
+            Self::Label(text)
+            | Self::Button(text, _)
+            | Self::TextInput { text, .. }
+            | Self::Slider { text, .. } => text,
+        }
+    }
+
+    fn action(&self) -> Option<A> {
+        match *self {
+            Self::Label(_) => None,
+            Self::Button(_, action)
+            | Self::TextInput { action, .. }
+            | Self::Slider { action, .. } => Some(action),
+        }
+    }
+}
+
+struct ScrollArea {
+    id: &'static str,
+    aabb: Aabb2<f32>,
+    first: usize,
+    rows: usize,
+    total: usize,
+}
+
+/// Widgets placed on the screen, used for both drawing and hit testing
+pub struct Layout<A> {
+    left: f32,
+    right: f32,
+    elements: Vec<(Aabb2<f32>, Element<A>)>,
+    lists: Vec<ScrollArea>,
+}
+
+impl<A: Copy + PartialEq> Layout<A> {
+    pub fn new(left: f32, right: f32) -> Self {
+        Self {
+            left,
+            right,
+            elements: Vec::new(),
+            lists: Vec::new(),
+        }
+    }
+
+    /// Stacks widgets downwards starting at `top`
+    pub fn column(&mut self, top: f32, widgets: Vec<Widget<A>>, ui: &Ui<A>) {
+        let mut y = top;
+        for widget in widgets {
+            let height = widget.height();
+            let aabb = Aabb2 {
+                min: vec2(self.left, y - height),
+                max: vec2(self.right, y),
+            };
+            self.place(widget, aabb, ui);
+            y -= height;
+        }
+    }
+
+    fn place(&mut self, widget: Widget<A>, aabb: Aabb2<f32>, ui: &Ui<A>) {
+        let element = match widget {
+            Widget::Label(text) => Element::Label(text),
+            Widget::Button(text, action) => Element::Button(text, action),
+            Widget::TextInput { id, text, action } => Element::TextInput {
+                text,
+                editing: ui.is_focused(id),
+                action,
+            },
+            Widget::Slider {
+                text,
+                value,
+                action,
+            } => Element::Slider {
+                text,
+                value,
+                action,
+            },
+            Widget::Row(widgets) => {
+                let width = aabb.width() / widgets.len() as f32;
+                for (i, widget) in widgets.into_iter().enumerate() {
+                    let left = aabb.min.x + width * i as f32;
+                    let aabb = Aabb2 {
+                        min: vec2(left, aabb.max.y - widget.height()),
+                        max: vec2(left + width, aabb.max.y),
+                    };
+                    self.place(widget, aabb, ui);
+                }
+                return;
+            }
+            Widget::List { id, rows, items } => {
+                let total = items.len();
+                let first = ui
+                    .scroll
+                    .get(id)
+                    .copied()
+                    .unwrap_or(0)
+                    .min(total.saturating_sub(rows));
+                self.lists.push(ScrollArea {
+                    id,
+                    aabb,
+                    first,
+                    rows,
+                    total,
+                });
+                let mut y = aabb.max.y;
+                for item in items.into_iter().skip(first).take(rows) {
+                    let height = item.height();
+                    let item_aabb = Aabb2 {
+                        min: vec2(aabb.min.x, y - height),
+                        max: vec2(aabb.max.x, y),
+                    };
+                    self.place(item, item_aabb, ui);
+                    y -= height;
+                }
+                return;
+            }
+        };
+        self.elements.push((aabb, element));
+    }
+
+    fn hit(&self, pos: vec2<f32>) -> Option<A> {
+        self.elements
+            .iter()
+            .find(|(aabb, _)| aabb.contains(pos))
+            .and_then(|(_, element)| element.action())
+    }
+
+    fn slider_value(&self, action: A, pos: vec2<f32>, config: &UiConfig) -> Option<f32> {
+        self.elements
+            .iter()
+            .find_map(|(aabb, element)| match element {
+                Element::Slider { action: a, .. } if *a == action => {
+                    let bar = slider_bar(*aabb, config);
+                    Some(((pos.x - bar.min.x) / bar.width()).clamp(0.0, 1.0))
+                }
+                _ => None,
+            })
+    }
+
+    fn draw(
+        &self,
+        geng: &Geng,
+        framebuffer: &mut ugli::Framebuffer,
+        camera: &Camera2d,
+        config: &UiConfig,
+        cursor: vec2<f32>,
+        show_caret: bool,
+    ) {
+        let font = geng.default_font();
+        for (aabb, element) in &self.elements {
+            let aabb = *aabb;
+            geng.draw2d()
+                .quad(framebuffer, camera, aabb, config.bg_color);
+            if let Element::Slider { value, .. } = *element {
+                let bar = slider_bar(aabb, config);
+                geng.draw2d()
+                    .quad(framebuffer, camera, bar, config.slider_color);
+                geng.draw2d().quad(
+                    framebuffer,
+                    camera,
+                    Aabb2 {
+                        max: vec2(bar.min.x + bar.width() * value, bar.max.y),
+                        ..bar
+                    },
+                    config.slider_fill_color,
+                );
+            }
+            let action = element.action();
+            if action.is_some() && aabb.contains(cursor) {
+                geng.draw2d()
+                    .quad(framebuffer, camera, aabb, config.hover_color);
+            }
+            let color = match action {
+                None => config.label_color,
+                Some(_) => config.button_color,
+            };
+            let text_pos = vec2(aabb.center().x, aabb.min.y + config.text_offset);
+            if let Element::TextInput { editing: true, .. } = element {
+                geng.draw2d()
+                    .quad(framebuffer, camera, aabb, config.edit_text_color);
+                if show_caret {
+                    let w = font
+                        .measure(
+                            element.text(),
+                            vec2(geng::TextAlign::CENTER, geng::TextAlign::BOTTOM),
+                        )
+                        .unwrap_or(Aabb2::ZERO);
+                    font.draw(
+                        framebuffer,
+                        camera,
+                        "|",
+                        vec2(geng::TextAlign::CENTER, geng::TextAlign::BOTTOM),
+                        mat3::translate(text_pos + vec2(w.max.x, 0.0)),
+                        color,
+                    );
+                }
+            }
+            font.draw(
+                framebuffer,
+                camera,
+                element.text(),
+                vec2(geng::TextAlign::CENTER, geng::TextAlign::BOTTOM),
+                mat3::translate(text_pos),
+                color,
+            );
+        }
+        for list in &self.lists {
+            if list.total <= list.rows {
+                continue;
+            }
+            let track = Aabb2::point(vec2(list.aabb.max.x, list.aabb.min.y))
+                .extend_left(config.scrollbar_width)
+                .extend_up(list.aabb.height());
+            let thumb_height = track.height() * list.rows as f32 / list.total as f32;
+            let thumb_top = track.max.y - track.height() * list.first as f32 / list.total as f32;
+            geng.draw2d().quad(
+                framebuffer,
+                camera,
+                Aabb2 {
+                    min: vec2(track.min.x, thumb_top - thumb_height),
+                    max: vec2(track.max.x, thumb_top),
+                },
+                config.scrollbar_color,
+            );
+        }
+    }
+}
+
+fn slider_bar(aabb: Aabb2<f32>, config: &UiConfig) -> Aabb2<f32> {
+    Aabb2::point(vec2(aabb.center().x, aabb.min.y + 0.1))
+        .extend_symmetric(vec2(config.slider_width.min(aabb.width() * 0.9) / 2.0, 0.0))
+        .extend_up(0.1)
+}